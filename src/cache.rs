@@ -0,0 +1,396 @@
+pub mod cache {
+    use crate::datagetter::datagetter::{
+        get_app_data_dir, FetchReport, Result, TradeData, TradeDataByType,
+    };
+    use crate::provider::provider::MarketDataProvider;
+    use async_trait::async_trait;
+    use chrono::{DateTime, Duration, SecondsFormat, Utc};
+    use rusqlite::{params, Connection as SQL_Connection};
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    /// Fetched prices, kept apart from the SDE `eve.db` in the user data dir.
+    /// Rows are keyed by market (the provider name, which carries the station) and type.
+    /// Types the market had no price for are kept as `missing` rows, so they are not asked
+    /// for again until they expire like any other row.
+    pub struct PriceCache {
+        conn: SQL_Connection,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct CachedPrices {
+        pub data: TradeDataByType,
+        /// Types the market had no price for when last asked.
+        pub missing: BTreeSet<i32>,
+    }
+
+    impl PriceCache {
+        pub fn open(path: &Path) -> Result<Self> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            PriceCache::init(SQL_Connection::open(path)?)
+        }
+
+        pub fn open_default() -> Result<Self> {
+            PriceCache::open(&get_app_data_dir().join("price_cache.db"))
+        }
+
+        #[cfg(test)]
+        pub fn open_in_memory() -> Result<Self> {
+            PriceCache::init(SQL_Connection::open_in_memory()?)
+        }
+
+        fn init(conn: SQL_Connection) -> Result<Self> {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS price_cache (
+                    market TEXT NOT NULL,
+                    type_id INTEGER NOT NULL,
+                    fetched_at INTEGER NOT NULL,
+                    updated TEXT NOT NULL,
+                    weekly_movement REAL NOT NULL,
+                    buy_max REAL NOT NULL,
+                    buy_listed INTEGER NOT NULL,
+                    sell_min REAL NOT NULL,
+                    sell_listed INTEGER NOT NULL,
+                    missing INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (market, type_id)
+                )",
+            )?;
+            Ok(PriceCache { conn })
+        }
+
+        pub fn store(&mut self, market: &str, trade_data: &TradeDataByType) -> Result<()> {
+            let fetched_at = Utc::now().timestamp();
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO price_cache
+                    (market, type_id, fetched_at, updated, weekly_movement,
+                     buy_max, buy_listed, sell_min, sell_listed, missing)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 0)",
+                )?;
+                for (type_id, td) in trade_data {
+                    stmt.execute(params![
+                        market,
+                        type_id,
+                        fetched_at,
+                        td.updated
+                            .map(|updated| updated.to_rfc3339_opts(SecondsFormat::Secs, true))
+                            .unwrap_or_default(),
+                        td.weekly_movement,
+                        td.buy_max,
+                        td.buy_listed,
+                        td.sell_min,
+                        td.sell_listed
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        }
+
+        /// Records that `market` had no price for `type_ids`.
+        pub fn store_missing(&mut self, market: &str, type_ids: &[i32]) -> Result<()> {
+            let fetched_at = Utc::now().timestamp();
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO price_cache
+                    (market, type_id, fetched_at, updated, weekly_movement,
+                     buy_max, buy_listed, sell_min, sell_listed, missing)
+                    VALUES (?1, ?2, ?3, '', 0, 0, 0, 0, 0, 1)",
+                )?;
+                for type_id in type_ids {
+                    stmt.execute(params![market, type_id, fetched_at])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        }
+
+        /// Cached rows of `type_ids`, skipping anything fetched longer than `max_age` ago.
+        /// `None` returns the whole last snapshot regardless of age.
+        pub fn get(
+            &self,
+            market: &str,
+            type_ids: &[i32],
+            max_age: Option<Duration>,
+        ) -> Result<CachedPrices> {
+            let oldest = match max_age {
+                Some(max_age) => (Utc::now() - max_age).timestamp(),
+                None => i64::MIN,
+            };
+            let mut stmt = self.conn.prepare(
+                "SELECT type_id, missing, updated, weekly_movement,
+                    buy_max, buy_listed, sell_min, sell_listed
+                FROM price_cache
+                WHERE market = ?1 AND fetched_at >= ?2
+                AND type_id IN (SELECT value FROM json_each(?3))",
+            )?;
+            let ids_json = serde_json::to_string(type_ids)?;
+            let mut rows = stmt.query(params![market, oldest, ids_json])?;
+
+            let mut result = CachedPrices::default();
+            while let Some(row) = rows.next()? {
+                let type_id: i32 = row.get(0)?;
+                if row.get(1)? {
+                    result.missing.insert(type_id);
+                    continue;
+                }
+                let updated: String = row.get(2)?;
+                result.data.insert(
+                    type_id,
+                    TradeData {
+                        updated: DateTime::parse_from_rfc3339(&updated)
+                            .ok()
+                            .map(|updated| updated.with_timezone(&Utc)),
+                        weekly_movement: row.get(3)?,
+                        buy_max: row.get(4)?,
+                        buy_listed: row.get(5)?,
+                        sell_min: row.get(6)?,
+                        sell_listed: row.get(7)?,
+                    },
+                );
+            }
+            Ok(result)
+        }
+    }
+
+    /// Serves prices from the cache while they are younger than `ttl` and only asks
+    /// the wrapped provider for the rest. Offline it never touches the wrapped provider.
+    pub struct CachedProvider {
+        inner: Box<dyn MarketDataProvider>,
+        cache: Arc<Mutex<PriceCache>>,
+        ttl: Duration,
+        offline: bool,
+    }
+
+    impl CachedProvider {
+        pub fn new(
+            inner: Box<dyn MarketDataProvider>,
+            cache: Arc<Mutex<PriceCache>>,
+            ttl: Duration,
+            offline: bool,
+        ) -> Self {
+            CachedProvider {
+                inner,
+                cache,
+                ttl,
+                offline,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl MarketDataProvider for CachedProvider {
+        fn name(&self) -> String {
+            self.inner.name()
+        }
+
+        async fn get_trade_data(&self, type_ids: &[i32]) -> Result<TradeDataByType> {
+            self.get_trade_data_report(type_ids).await.into_result()
+        }
+
+        /// Cache hits never fail. Whatever the wrapped provider did fetch is cached
+        /// even when some of its batches failed, and so are the types it had no price for
+        /// outside the failed batches.
+        async fn get_trade_data_report(&self, type_ids: &[i32]) -> FetchReport<TradeDataByType> {
+            let started = Instant::now();
+            let market = self.inner.name();
+            let max_age = if self.offline { None } else { Some(self.ttl) };
+            let cached = self
+                .cache
+                .lock()
+                .expect("price cache lock poisoned")
+                .get(&market, type_ids, max_age);
+            let cached = cached.unwrap_or_else(|e| {
+                log::warn!("price cache of {} unreadable: {}", market, e);
+                CachedPrices::default()
+            });
+            let mut report = FetchReport {
+                data: cached.data,
+                failed_batches: vec![],
+                elapsed: started.elapsed(),
+            };
+            if self.offline {
+                return report;
+            }
+
+            let missing: Vec<i32> = type_ids
+                .iter()
+                .filter(|id| !report.data.contains_key(id) && !cached.missing.contains(id))
+                .cloned()
+                .collect();
+            if missing.is_empty() {
+                return report;
+            }
+
+            let fetched = self.inner.get_trade_data_report(&missing).await;
+            let failed: BTreeSet<i32> = fetched.failed_type_ids().into_iter().collect();
+            let unpriced: Vec<i32> = missing
+                .iter()
+                .filter(|id| !fetched.data.contains_key(id) && !failed.contains(id))
+                .cloned()
+                .collect();
+            let mut cache = self.cache.lock().expect("price cache lock poisoned");
+            if let Err(e) = cache
+                .store(&market, &fetched.data)
+                .and_then(|_| cache.store_missing(&market, &unpriced))
+            {
+                log::warn!("cannot cache prices of {}: {}", market, e);
+            }
+            drop(cache);
+            report.merge_retry(fetched);
+            report.elapsed = started.elapsed();
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::cache::*;
+    use crate::datagetter::datagetter::{Result, TradeData, TradeDataByType};
+    use crate::provider::provider::MarketDataProvider;
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn trade_data(sell_min: f64) -> TradeData {
+        TradeData {
+            updated: "2024-05-03T13:36:22Z".parse().ok(),
+            weekly_movement: 3.0,
+            buy_max: 2.0,
+            buy_listed: 3,
+            sell_min,
+            sell_listed: 3,
+        }
+    }
+
+    struct CountingProvider {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl MarketDataProvider for CountingProvider {
+        fn name(&self) -> String {
+            "counting:60003760".to_owned()
+        }
+
+        /// Nobody trades types from 1000 up on this market.
+        async fn get_trade_data(&self, type_ids: &[i32]) -> Result<TradeDataByType> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(type_ids
+                .iter()
+                .filter(|id| **id < 1000)
+                .map(|id| (*id, trade_data(9.0)))
+                .collect())
+        }
+    }
+
+    fn cached_provider(
+        cache: &Arc<Mutex<PriceCache>>,
+        ttl: Duration,
+        offline: bool,
+    ) -> (CachedProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = Box::new(CountingProvider {
+            calls: calls.clone(),
+        });
+        (
+            CachedProvider::new(inner, cache.clone(), ttl, offline),
+            calls,
+        )
+    }
+
+    #[test]
+    fn cache_roundtrip_honours_max_age() {
+        let mut cache = PriceCache::open_in_memory().unwrap();
+        let data: TradeDataByType = [(34, trade_data(5.0))].into_iter().collect();
+        cache.store("goonmetrics:60003760", &data).unwrap();
+
+        let fresh = cache
+            .get(
+                "goonmetrics:60003760",
+                &[34, 35],
+                Some(Duration::minutes(5)),
+            )
+            .unwrap();
+        assert_eq!(fresh.data, data);
+        assert!(fresh.missing.is_empty());
+        assert_eq!(
+            cache.get("goonmetrics:1030049082711", &[34], None).unwrap(),
+            CachedPrices::default()
+        );
+        assert_eq!(
+            cache
+                .get("goonmetrics:60003760", &[34], Some(Duration::seconds(-5)))
+                .unwrap(),
+            CachedPrices::default()
+        );
+
+        cache.store_missing("goonmetrics:60003760", &[35]).unwrap();
+        let fresh = cache
+            .get("goonmetrics:60003760", &[34, 35], Some(Duration::minutes(5)))
+            .unwrap();
+        assert_eq!(fresh.data, data);
+        assert_eq!(fresh.missing, [35].into());
+    }
+
+    #[tokio::test]
+    async fn cached_provider_fetches_only_missing_types() {
+        let cache = Arc::new(Mutex::new(PriceCache::open_in_memory().unwrap()));
+        let stored: TradeDataByType = [(34, trade_data(5.0))].into_iter().collect();
+        cache
+            .lock()
+            .unwrap()
+            .store("counting:60003760", &stored)
+            .unwrap();
+
+        let (provider, calls) = cached_provider(&cache, Duration::minutes(60), false);
+        let result = provider.get_trade_data(&[34, 35]).await.unwrap();
+        assert_eq!(result[&34].sell_min, 5.0);
+        assert_eq!(result[&35].sell_min, 9.0);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        provider.get_trade_data(&[34, 35]).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn offline_provider_serves_stale_snapshot_without_fetching() {
+        let cache = Arc::new(Mutex::new(PriceCache::open_in_memory().unwrap()));
+        let stored: TradeDataByType = [(34, trade_data(5.0))].into_iter().collect();
+        cache
+            .lock()
+            .unwrap()
+            .store("counting:60003760", &stored)
+            .unwrap();
+
+        let (provider, calls) = cached_provider(&cache, Duration::seconds(-5), true);
+        let result = provider.get_trade_data(&[34, 35]).await.unwrap();
+        assert_eq!(result, stored);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn types_without_prices_are_not_refetched_within_ttl() {
+        let cache = Arc::new(Mutex::new(PriceCache::open_in_memory().unwrap()));
+
+        let (provider, calls) = cached_provider(&cache, Duration::minutes(60), false);
+        let result = provider.get_trade_data(&[34, 1001]).await.unwrap();
+        assert_eq!(result.keys().collect::<Vec<_>>(), vec![&34]);
+        provider.get_trade_data(&[34, 1001]).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Expired like any other row.
+        let (expired, calls) = cached_provider(&cache, Duration::seconds(-5), false);
+        expired.get_trade_data(&[1001]).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
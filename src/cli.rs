@@ -0,0 +1,221 @@
+pub mod cli {
+    use crate::datagetter::datagetter::ItemMetadataFilter;
+    use crate::filters::filters::OpportunityFilter;
+    use clap::{Parser, Subcommand, ValueEnum};
+    use std::path::PathBuf;
+
+    #[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+    pub enum PriceSource {
+        /// goonmetrics price_data summaries
+        Goonmetrics,
+        /// public ESI regional order book
+        Esi,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+    pub enum AbroadPriceSource {
+        /// goonmetrics price_data summaries
+        Goonmetrics,
+        /// authenticated ESI structure market, needs EVE SSO login
+        EsiStructure,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+    pub enum DailyVolumeEstimate {
+        /// weekly movement scaled down by how overstocked the market is
+        Heuristic,
+        /// average of real ESI market history, heuristic where none exists
+        History,
+    }
+
+    #[derive(Debug, Subcommand)]
+    pub enum Command {
+        /// Install a Fuzzwork SDE dump (sqlite-latest.sqlite.bz2 or the CSV directory)
+        ImportSde {
+            /// Downloaded archive, decompressed sqlite file or CSV dump directory
+            source: PathBuf,
+            /// Recorded as the SDE build, defaults to the import date and file name
+            #[arg(long)]
+            sde_version: Option<String>,
+        },
+        /// Restore the SDE replaced by the last import
+        RollbackSde,
+    }
+
+    #[derive(Debug, Parser)]
+    #[command(version, about = "Jita -> abroad hauling opportunities")]
+    pub struct Cli {
+        #[command(subcommand)]
+        pub command: Option<Command>,
+        /// Where prices at the source hubs come from
+        #[arg(long, value_enum, default_value_t = PriceSource::Goonmetrics)]
+        pub jita_source: PriceSource,
+        /// Where prices at the destinations come from
+        #[arg(long, value_enum, default_value_t = AbroadPriceSource::Goonmetrics)]
+        pub abroad_source: AbroadPriceSource,
+        /// EVE SSO application client ID, needed for structure markets
+        #[arg(long, env = "EVE_SSO_CLIENT_ID")]
+        pub sso_client_id: Option<String>,
+        /// How abroad daily sales are estimated, always history for markets priced from ESI
+        #[arg(long, value_enum, default_value_t = DailyVolumeEstimate::Heuristic)]
+        pub daily_volume: DailyVolumeEstimate,
+        /// Region for ESI market history at destinations that do not set region_id
+        #[arg(long)]
+        pub abroad_region_id: Option<i32>,
+        /// Days of market history the estimate is computed over
+        #[arg(long, default_value_t = 30)]
+        pub history_days: i64,
+        /// Minutes fetched prices stay fresh in the local price cache
+        #[arg(long, default_value_t = 60)]
+        pub cache_ttl_minutes: i64,
+        /// Minutes since the market last saw a quote before it counts as stale
+        #[arg(long, default_value_t = 180)]
+        pub max_quote_age_minutes: i64,
+        /// Leave items with a stale Jita or abroad quote out of the table instead of marking them
+        #[arg(long)]
+        pub hide_stale: bool,
+        /// Price batch requests in flight at the same time, per market
+        #[arg(long, default_value_t = 8, value_parser = at_least_one)]
+        pub max_concurrent_requests: usize,
+        /// Seconds before a single price request is given up
+        #[arg(long, default_value_t = 30)]
+        pub request_timeout_secs: u64,
+        /// Retries per request after network errors, 5xx, rate limits and garbage bodies
+        #[arg(long, default_value_t = 4)]
+        pub max_retries: u32,
+        /// Requests per second across all markets and providers
+        #[arg(long, default_value_t = 20.0, value_parser = positive_rate)]
+        pub requests_per_second: f64,
+        /// Settings file; defaults to config.toml in the user data dir, generated on first run
+        #[arg(long, global = true)]
+        pub config: Option<PathBuf>,
+        /// Named profile from the config's [profiles] table to run with
+        #[arg(long, global = true)]
+        pub profile: Option<String>,
+        /// SDE sqlite dump; defaults to GESCHEFTMACHER_SDE_PATH, then eve.db in the user data dir
+        #[arg(long, global = true)]
+        pub sde_path: Option<PathBuf>,
+        /// Only look at these type IDs instead of every tradable item (repeatable)
+        #[arg(long = "type-id")]
+        pub type_ids: Vec<i32>,
+        /// Only look at items under this market group and its subgroups, e.g. "Ships > Frigates" (repeatable)
+        #[arg(long = "market-group")]
+        pub market_groups: Vec<String>,
+        /// Only look at items in this inventory category, e.g. "Module" (repeatable)
+        #[arg(long = "category")]
+        pub categories: Vec<String>,
+        /// Only look at items in this inventory group, e.g. "Shield Hardener" (repeatable)
+        #[arg(long = "group")]
+        pub groups: Vec<String>,
+        /// Only look at items in this meta group, e.g. "Tech II" or "Faction" (repeatable)
+        #[arg(long = "meta-group")]
+        pub meta_groups: Vec<String>,
+        /// Only look at items of this tech level (repeatable)
+        #[arg(long = "tech-level")]
+        pub tech_levels: Vec<i32>,
+        #[arg(long)]
+        pub min_meta_level: Option<i32>,
+        #[arg(long)]
+        pub max_meta_level: Option<i32>,
+        /// Show every item even when the config turns on the opportunity thresholds below
+        #[arg(long)]
+        pub show_all: bool,
+        /// Overrides thresholds.min_sell_margin from the config
+        #[arg(long)]
+        pub min_sell_margin: Option<f64>,
+        /// Overrides thresholds.min_daily_profit from the config
+        #[arg(long)]
+        pub min_daily_profit: Option<f64>,
+        /// Overrides thresholds.min_freeze_rate from the config
+        #[arg(long)]
+        pub min_freeze_rate: Option<f64>,
+        /// Overrides thresholds.max_market_rate from the config
+        #[arg(long)]
+        pub max_market_rate: Option<f64>,
+        /// Overrides thresholds.min_daily_volume from the config
+        #[arg(long)]
+        pub min_daily_volume: Option<f64>,
+        /// Price exports from the destinations back to Jita instead of imports, hauled at the
+        /// config's [reverse] freight
+        #[arg(long)]
+        pub reverse: bool,
+        /// Run from the last cached prices and stored history, without any network access
+        #[arg(long)]
+        pub offline: bool,
+    }
+
+    /// No request would ever be let through with zero in flight.
+    fn at_least_one(value: &str) -> Result<usize, String> {
+        match value.parse::<usize>() {
+            Ok(count) if count >= 1 => Ok(count),
+            Ok(count) => Err(format!("must be at least 1, got {}", count)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn positive_rate(value: &str) -> Result<f64, String> {
+        match value.parse::<f64>() {
+            Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+            Ok(rate) => Err(format!("must be above 0, got {}", rate)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    impl Cli {
+        /// `configured` with every criterion given on the command line replaced.
+        pub fn item_metadata_filter(&self, configured: ItemMetadataFilter) -> ItemMetadataFilter {
+            let or_configured = |flag: &Vec<String>, configured: Vec<String>| {
+                if flag.is_empty() {
+                    configured
+                } else {
+                    flag.clone()
+                }
+            };
+            ItemMetadataFilter {
+                categories: or_configured(&self.categories, configured.categories),
+                groups: or_configured(&self.groups, configured.groups),
+                meta_groups: or_configured(&self.meta_groups, configured.meta_groups),
+                tech_levels: if self.tech_levels.is_empty() {
+                    configured.tech_levels
+                } else {
+                    self.tech_levels.clone()
+                },
+                min_meta_level: self.min_meta_level.or(configured.min_meta_level),
+                max_meta_level: self.max_meta_level.or(configured.max_meta_level),
+            }
+        }
+
+        /// The configured thresholds with whatever was given on the command line.
+        pub fn opportunity_filter(&self, configured: &OpportunityFilter) -> OpportunityFilter {
+            let defaults = configured.clone();
+            OpportunityFilter {
+                enabled: defaults.enabled && !self.show_all,
+                min_sell_margin: self.min_sell_margin.unwrap_or(defaults.min_sell_margin),
+                min_daily_profit: self.min_daily_profit.unwrap_or(defaults.min_daily_profit),
+                min_freeze_rate: self.min_freeze_rate.unwrap_or(defaults.min_freeze_rate),
+                max_market_rate: self.max_market_rate.unwrap_or(defaults.max_market_rate),
+                min_daily_volume: self.min_daily_volume.unwrap_or(defaults.min_daily_volume),
+                ..defaults
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::cli::*;
+    use clap::Parser;
+
+    #[test]
+    fn request_limits_that_would_stall_are_rejected() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["gescheftmacher"], args].concat());
+
+        assert!(parse(&["--requests-per-second", "0"]).is_err());
+        assert!(parse(&["--requests-per-second", "-5"]).is_err());
+        assert!(parse(&["--max-concurrent-requests", "0"]).is_err());
+
+        let cli = parse(&["--requests-per-second", "0.5", "--max-concurrent-requests", "1"]).unwrap();
+        assert_eq!(cli.requests_per_second, 0.5);
+        assert_eq!(cli.max_concurrent_requests, 1);
+    }
+}
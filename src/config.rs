@@ -0,0 +1,753 @@
+pub mod config {
+    use crate::datagetter::datagetter::{get_app_data_dir, ItemMetadataFilter, Result};
+    use crate::esi::esi::THE_FORGE_REGION_ID;
+    use crate::fees::fees::FeesConfig;
+    use crate::filters::filters::OpportunityFilter;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::{Path, PathBuf};
+
+    pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+    /// Everything the trade math depends on. Missing keys fall back to the defaults,
+    /// unknown ones are an error so a typo does not silently keep the default.
+    #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        pub stations: StationsConfig,
+        pub fees: FeesConfig,
+        pub freight: FreightConfig,
+        /// Markets to buy at. Without any, `stations.jita_station_id` is the only one.
+        pub sources: Vec<SourceConfig>,
+        /// Markets to haul to. Without any, `stations.abroad_location_id` is the only one.
+        pub destinations: Vec<DestinationConfig>,
+        pub reverse: ReverseConfig,
+        pub thresholds: OpportunityFilter,
+        pub items: ItemsConfig,
+        pub sde: SdeConfig,
+        /// Named overrides of the settings above, e.g. `[profiles.jf.freight]`. Only the keys
+        /// a profile sets change, everything else comes from the base config.
+        pub profiles: BTreeMap<String, toml::Table>,
+    }
+
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct StationsConfig {
+        /// Jita IV - Moon 4 - Caldari Navy Assembly Plant. Only used when the config lists
+        /// no `[[sources]]`.
+        pub jita_station_id: i64,
+        pub jita_region_id: i32,
+        /// Where the goods are sold, a station or a player structure. Only used when the
+        /// config lists no `[[destinations]]`.
+        pub abroad_location_id: i64,
+        /// Needed for ESI market history, `--abroad-region-id` stands in when unset.
+        pub abroad_region_id: Option<i32>,
+    }
+
+    impl Default for StationsConfig {
+        fn default() -> Self {
+            StationsConfig {
+                jita_station_id: 60003760,
+                jita_region_id: THE_FORGE_REGION_ID,
+                abroad_location_id: 1030049082711,
+                abroad_region_id: None,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct FreightConfig {
+        pub price_per_m3: f64,
+        /// Collateral fee as a fraction of what the goods cost at the source.
+        pub collateral_rate: f64,
+    }
+
+    impl Default for FreightConfig {
+        fn default() -> Self {
+            FreightConfig {
+                price_per_m3: 850.0,
+                collateral_rate: 0.0,
+            }
+        }
+    }
+
+    /// A market goods are hauled to. Whatever it leaves out comes from `[fees]` and `[freight]`.
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct DestinationConfig {
+        pub name: String,
+        pub location_id: i64,
+        #[serde(default)]
+        pub region_id: Option<i32>,
+        /// Replaces the broker fee the fee model computes for this location.
+        #[serde(default)]
+        pub broker_fee: Option<f64>,
+        /// Replaces the sales tax the fee model computes.
+        #[serde(default)]
+        pub sales_tax: Option<f64>,
+        #[serde(default)]
+        pub freight: Option<FreightConfig>,
+    }
+
+    /// A destination with everything it inherits filled in.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Destination {
+        pub name: String,
+        pub location_id: i64,
+        pub region_id: Option<i32>,
+        pub broker_fee: f64,
+        pub sales_tax: f64,
+        pub freight: FreightConfig,
+    }
+
+    impl Destination {
+        /// Placing the sell order and the sales tax once it fills.
+        pub fn sell_fee(&self) -> f64 {
+            self.broker_fee + self.sales_tax
+        }
+    }
+
+    pub const DEFAULT_DESTINATION_NAME: &str = "abroad";
+
+    /// A hub goods are bought at with buy orders.
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct SourceConfig {
+        pub name: String,
+        pub location_id: i64,
+        /// Needed when Jita prices come from ESI, which is queried by region.
+        #[serde(default)]
+        pub region_id: Option<i32>,
+        /// Replaces the broker fee the fee model computes for this location.
+        #[serde(default)]
+        pub broker_fee: Option<f64>,
+        /// Hauling cost to each destination by name. Unlisted destinations use their own
+        /// freight, and nothing is charged when the source is the destination itself.
+        #[serde(default)]
+        pub freight: BTreeMap<String, FreightConfig>,
+    }
+
+    /// A source with everything it inherits filled in.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Source {
+        pub name: String,
+        pub location_id: i64,
+        pub region_id: Option<i32>,
+        pub broker_fee: f64,
+        pub freight: BTreeMap<String, FreightConfig>,
+    }
+
+    impl Source {
+        pub fn freight_to(&self, destination: &Destination) -> FreightConfig {
+            match self.freight.get(&destination.name) {
+                Some(freight) => freight.clone(),
+                None if self.location_id == destination.location_id => FreightConfig {
+                    price_per_m3: 0.0,
+                    collateral_rate: 0.0,
+                },
+                None => destination.freight.clone(),
+            }
+        }
+    }
+
+    pub const DEFAULT_SOURCE_NAME: &str = "jita";
+
+    /// Exports with `--reverse`: from every destination back to Jita.
+    #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ReverseConfig {
+        /// Hauling cost from any destination to Jita.
+        pub freight: FreightConfig,
+    }
+
+    pub const EXIT_MARKET_NAME: &str = "jita";
+
+    /// Which items to load; the command line flags of the same names replace these.
+    #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ItemsConfig {
+        /// Market group paths such as "Ships > Frigates".
+        pub market_groups: Vec<String>,
+        pub categories: Vec<String>,
+        pub groups: Vec<String>,
+        pub meta_groups: Vec<String>,
+        pub tech_levels: Vec<i32>,
+        pub min_meta_level: Option<i32>,
+        pub max_meta_level: Option<i32>,
+    }
+
+    impl ItemsConfig {
+        pub fn metadata_filter(&self) -> ItemMetadataFilter {
+            ItemMetadataFilter {
+                categories: self.categories.clone(),
+                groups: self.groups.clone(),
+                meta_groups: self.meta_groups.clone(),
+                tech_levels: self.tech_levels.clone(),
+                min_meta_level: self.min_meta_level,
+                max_meta_level: self.max_meta_level,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct SdeConfig {
+        /// Used when neither `--sde-path` nor GESCHEFTMACHER_SDE_PATH is set.
+        pub path: Option<PathBuf>,
+    }
+
+    /// Sets every key of `overrides` in `base`, descending into tables both have.
+    fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+        for (key, value) in overrides {
+            match (base.get_mut(&key), value) {
+                (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                    merge_tables(base, overrides)
+                }
+                (_, value) => {
+                    base.insert(key, value);
+                }
+            }
+        }
+    }
+
+    pub fn default_config_path() -> PathBuf {
+        get_app_data_dir().join(CONFIG_FILE_NAME)
+    }
+
+    impl Config {
+        /// Reads `path`, or the default location when `None`. Only the default location
+        /// gets a fresh config written on first run; a missing `--config` file is an error.
+        pub fn load_or_create(path: Option<PathBuf>) -> Result<(Config, PathBuf)> {
+            let explicit = path.is_some();
+            let path = path.unwrap_or_else(default_config_path);
+            if !path.exists() {
+                if explicit {
+                    return Err(format!("config file {:?} does not exist", path).into());
+                }
+                Config::write_default(&path)?;
+            }
+            Ok((Config::load(&path)?, path))
+        }
+
+        pub fn load(path: &Path) -> Result<Config> {
+            let text = std::fs::read_to_string(path)?;
+            Config::parse(&text).map_err(|e| format!("config file {:?}: {}", path, e).into())
+        }
+
+        pub fn parse(text: &str) -> Result<Config> {
+            let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+            config.validate()?;
+            for name in config.profiles.keys() {
+                config.profile(name)?;
+            }
+            Ok(config)
+        }
+
+        /// Every destination in config order, or the `stations` one when none are listed.
+        pub fn destinations(&self) -> Vec<Destination> {
+            let fees = &self.fees;
+            let resolve = |destination: &DestinationConfig| Destination {
+                name: destination.name.clone(),
+                location_id: destination.location_id,
+                region_id: destination.region_id,
+                broker_fee: destination.broker_fee.unwrap_or_else(|| {
+                    fees.broker_fee_at(destination.location_id, &fees.abroad_standings)
+                }),
+                sales_tax: destination.sales_tax.unwrap_or_else(|| fees.sales_tax()),
+                freight: destination
+                    .freight
+                    .clone()
+                    .unwrap_or_else(|| self.freight.clone()),
+            };
+            if self.destinations.is_empty() {
+                return vec![resolve(&DestinationConfig {
+                    name: DEFAULT_DESTINATION_NAME.to_owned(),
+                    location_id: self.stations.abroad_location_id,
+                    region_id: self.stations.abroad_region_id,
+                    broker_fee: None,
+                    sales_tax: None,
+                    freight: None,
+                })];
+            }
+            self.destinations.iter().map(resolve).collect()
+        }
+
+        /// Every source in config order, or Jita from `stations` when none are listed.
+        pub fn sources(&self) -> Vec<Source> {
+            if self.sources.is_empty() {
+                let station_id = self.stations.jita_station_id;
+                return vec![Source {
+                    name: DEFAULT_SOURCE_NAME.to_owned(),
+                    location_id: station_id,
+                    region_id: Some(self.stations.jita_region_id),
+                    broker_fee: self.fees.jita_buy_fee(station_id),
+                    freight: BTreeMap::new(),
+                }];
+            }
+            let fees = &self.fees;
+            self.sources
+                .iter()
+                .map(|source| Source {
+                    name: source.name.clone(),
+                    location_id: source.location_id,
+                    region_id: source.region_id,
+                    broker_fee: source.broker_fee.unwrap_or_else(|| {
+                        fees.broker_fee_at(source.location_id, &fees.jita_standings)
+                    }),
+                    freight: source.freight.clone(),
+                })
+                .collect()
+        }
+
+        /// The export route: every destination becomes a source, and Jita the only
+        /// destination, hauled at `[reverse]` freight. Everything else stays as it is.
+        pub fn reversed(&self) -> Config {
+            let sources = self
+                .destinations()
+                .into_iter()
+                .map(|destination| SourceConfig {
+                    name: destination.name,
+                    location_id: destination.location_id,
+                    region_id: destination.region_id,
+                    broker_fee: Some(destination.broker_fee),
+                    freight: BTreeMap::new(),
+                })
+                .collect();
+            let jita_station_id = self.stations.jita_station_id;
+            let exit = DestinationConfig {
+                name: EXIT_MARKET_NAME.to_owned(),
+                location_id: jita_station_id,
+                region_id: Some(self.stations.jita_region_id),
+                broker_fee: Some(self.fees.jita_buy_fee(jita_station_id)),
+                sales_tax: None,
+                freight: Some(self.reverse.freight.clone()),
+            };
+            Config {
+                sources,
+                destinations: vec![exit],
+                ..self.clone()
+            }
+        }
+
+        /// Every source and destination, the locations prices are fetched for.
+        pub fn market_locations(&self) -> BTreeSet<i64> {
+            let sources = self.sources().into_iter().map(|s| s.location_id);
+            let destinations = self.destinations().into_iter().map(|d| d.location_id);
+            sources.chain(destinations).collect()
+        }
+
+        pub fn profile_names(&self) -> Vec<String> {
+            self.profiles.keys().cloned().collect()
+        }
+
+        /// This config with the named profile laid over it. The result has no profiles of its
+        /// own, switching again goes through the base config.
+        pub fn profile(&self, name: &str) -> Result<Config> {
+            let overrides = self.profiles.get(name).ok_or_else(|| {
+                format!(
+                    "unknown profile {:?}, the config has: {}",
+                    name,
+                    self.profile_names().join(", ")
+                )
+            })?;
+            let in_profile = |e: String| format!("profile {:?}: {}", name, e);
+            if overrides.contains_key("profiles") {
+                return Err(in_profile("profiles cannot be nested".to_owned()).into());
+            }
+            let base = Config {
+                profiles: BTreeMap::new(),
+                ..self.clone()
+            };
+            let mut table = toml::Table::try_from(&base).map_err(|e| in_profile(e.to_string()))?;
+            merge_tables(&mut table, overrides.clone());
+            let config: Config = toml::Value::Table(table)
+                .try_into()
+                .map_err(|e: toml::de::Error| in_profile(e.to_string()))?;
+            config.validate().map_err(|e| in_profile(e.to_string()))?;
+            Ok(config)
+        }
+
+        pub fn write_default(path: &Path) -> Result<()> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let body = toml::to_string_pretty(&Config::default()).map_err(|e| e.to_string())?;
+            std::fs::write(
+                path,
+                format!(
+                    "# gescheftmacher settings, generated on first run.\n\
+                    # Fees and rates are fractions: 0.03 is 3%.\n\
+                    # Profiles override parts of it and are picked with --profile, e.g.\n\
+                    # [profiles.jf.freight]\n\
+                    # price_per_m3 = 600.0\n\n{}",
+                    body
+                ),
+            )?;
+            Ok(())
+        }
+
+        /// Every problem at once, so fixing the file takes one round trip.
+        pub fn validate(&self) -> Result<()> {
+            let mut problems = vec![];
+            let mut fraction = |key: &str, value: f64| {
+                if !(0.0..1.0).contains(&value) {
+                    problems.push(format!(
+                        "{} must be at least 0 and below 1, got {}",
+                        key, value
+                    ));
+                }
+            };
+            fraction("freight.collateral_rate", self.freight.collateral_rate);
+            fraction(
+                "reverse.freight.collateral_rate",
+                self.reverse.freight.collateral_rate,
+            );
+            let mut non_negative = vec![
+                (
+                    "freight.price_per_m3".to_owned(),
+                    self.freight.price_per_m3,
+                ),
+                (
+                    "reverse.freight.price_per_m3".to_owned(),
+                    self.reverse.freight.price_per_m3,
+                ),
+            ];
+            let destination_names: BTreeSet<&String> =
+                self.destinations.iter().map(|d| &d.name).collect();
+            let mut source_locations = BTreeSet::new();
+            for (i, source) in self.sources.iter().enumerate() {
+                let key = format!("sources[{}]", i);
+                if source.name.trim().is_empty() {
+                    problems.push(format!("{}.name must not be empty", key));
+                }
+                if source.location_id <= 0 {
+                    problems.push(format!(
+                        "{}.location_id must be a positive ID, got {}",
+                        key, source.location_id
+                    ));
+                }
+                if !source_locations.insert(source.location_id) {
+                    problems.push(format!(
+                        "{}.location_id {} is listed twice",
+                        key, source.location_id
+                    ));
+                }
+                if let Some(fee) = source.broker_fee.filter(|fee| !(0.0..1.0).contains(fee)) {
+                    problems.push(format!(
+                        "{}.broker_fee must be at least 0 and below 1, got {}",
+                        key, fee
+                    ));
+                }
+                for (name, freight) in &source.freight {
+                    let freight_key = format!("{}.freight.{}", key, name);
+                    let known = destination_names.contains(name)
+                        || (self.destinations.is_empty() && name == DEFAULT_DESTINATION_NAME);
+                    if !known {
+                        problems.push(format!("{} is not a destination", freight_key));
+                    }
+                    if !(0.0..1.0).contains(&freight.collateral_rate) {
+                        problems.push(format!(
+                            "{}.collateral_rate must be at least 0 and below 1, got {}",
+                            freight_key, freight.collateral_rate
+                        ));
+                    }
+                    let price_key = format!("{}.price_per_m3", freight_key);
+                    non_negative.push((price_key, freight.price_per_m3));
+                }
+            }
+            let mut locations = BTreeSet::new();
+            for (i, destination) in self.destinations.iter().enumerate() {
+                let key = format!("destinations[{}]", i);
+                if destination.name.trim().is_empty() {
+                    problems.push(format!("{}.name must not be empty", key));
+                }
+                if destination.location_id <= 0 {
+                    problems.push(format!(
+                        "{}.location_id must be a positive ID, got {}",
+                        key, destination.location_id
+                    ));
+                }
+                if !locations.insert(destination.location_id) {
+                    problems.push(format!(
+                        "{}.location_id {} is listed twice",
+                        key, destination.location_id
+                    ));
+                }
+                for (rate, value) in [
+                    ("broker_fee", destination.broker_fee),
+                    ("sales_tax", destination.sales_tax),
+                ] {
+                    if let Some(value) = value.filter(|value| !(0.0..1.0).contains(value)) {
+                        problems.push(format!(
+                            "{}.{} must be at least 0 and below 1, got {}",
+                            key, rate, value
+                        ));
+                    }
+                }
+                if let Some(freight) = &destination.freight {
+                    if !(0.0..1.0).contains(&freight.collateral_rate) {
+                        problems.push(format!(
+                            "{}.freight.collateral_rate must be at least 0 and below 1, got {}",
+                            key, freight.collateral_rate
+                        ));
+                    }
+                    let price_key = format!("{}.freight.price_per_m3", key);
+                    non_negative.push((price_key, freight.price_per_m3));
+                }
+            }
+            problems.extend(self.fees.problems());
+            let thresholds = &self.thresholds;
+            for (key, value) in non_negative.into_iter().chain([
+                ("thresholds.min_sell_margin", thresholds.min_sell_margin),
+                ("thresholds.min_daily_profit", thresholds.min_daily_profit),
+                ("thresholds.min_freeze_rate", thresholds.min_freeze_rate),
+                ("thresholds.max_market_rate", thresholds.max_market_rate),
+                ("thresholds.min_daily_volume", thresholds.min_daily_volume),
+            ]
+            .map(|(key, value)| (key.to_owned(), value)))
+            {
+                if !(value >= 0.0 && value.is_finite()) {
+                    problems.push(format!(
+                        "{} must be a non-negative number, got {}",
+                        key, value
+                    ));
+                }
+            }
+            for (key, value) in [
+                ("stations.jita_station_id", self.stations.jita_station_id),
+                (
+                    "stations.abroad_location_id",
+                    self.stations.abroad_location_id,
+                ),
+                (
+                    "stations.jita_region_id",
+                    self.stations.jita_region_id as i64,
+                ),
+            ] {
+                if value <= 0 {
+                    problems.push(format!("{} must be a positive ID, got {}", key, value));
+                }
+            }
+            if let (Some(min), Some(max)) = (self.items.min_meta_level, self.items.max_meta_level) {
+                if min > max {
+                    problems.push(format!(
+                        "items.min_meta_level ({}) is above items.max_meta_level ({})",
+                        min, max
+                    ));
+                }
+            }
+            if problems.is_empty() {
+                return Ok(());
+            }
+            Err(problems.join("\n").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::config::*;
+    use crate::fees::fees::FeesConfig;
+
+    #[test]
+    fn generated_default_round_trips() {
+        let path = std::env::temp_dir().join(format!(
+            "gescheftmacher-config-{}/{}",
+            std::process::id(),
+            CONFIG_FILE_NAME
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        Config::write_default(&path).unwrap();
+
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn partial_config_keeps_defaults() {
+        let config = Config::parse(
+            "[freight]
+            price_per_m3 = 1200.0
+
+            [thresholds]
+            min_daily_profit = 5000000.0",
+        )
+        .unwrap();
+
+        assert_eq!(config.freight.price_per_m3, 1200.0);
+        assert_eq!(config.thresholds.min_daily_profit, 5_000_000.0);
+        assert_eq!(config.fees, FeesConfig::default());
+    }
+
+    #[test]
+    fn invalid_config_names_every_problem() {
+        let typo = Config::parse("[freight]\nprice_per_m2 = 1.0")
+            .err()
+            .unwrap();
+        assert!(typo.to_string().contains("price_per_m2"));
+
+        let wrong_type = Config::parse("[fees]\nbase_sales_tax = \"7.5%\"")
+            .err()
+            .unwrap();
+        assert!(wrong_type.to_string().contains("base_sales_tax"));
+
+        let out_of_range = Config::parse(
+            "[fees]
+            base_sales_tax = 7.5
+
+            [freight]
+            price_per_m3 = -1.0",
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(out_of_range.contains("fees.base_sales_tax must be at least 0 and below 1"));
+        assert!(out_of_range.contains("freight.price_per_m3 must be a non-negative number"));
+    }
+
+    #[test]
+    fn profiles_override_only_what_they_set() {
+        let config = Config::parse(
+            "[freight]
+            price_per_m3 = 1200.0
+            collateral_rate = 0.01
+
+            [profiles.jf.freight]
+            price_per_m3 = 600.0
+
+            [profiles.other_keep.stations]
+            abroad_location_id = 1022734985679",
+        )
+        .unwrap();
+
+        let jf = config.profile("jf").unwrap();
+        assert_eq!(jf.freight.price_per_m3, 600.0);
+        assert_eq!(jf.freight.collateral_rate, 0.01);
+        assert_eq!(config.profile_names(), vec!["jf", "other_keep"]);
+
+        let other_keep = config.profile("other_keep").unwrap();
+        assert_eq!(other_keep.freight.price_per_m3, 1200.0);
+        assert_eq!(other_keep.stations.abroad_location_id, 1022734985679);
+
+        let unknown = config.profile("courier").err().unwrap();
+        assert!(unknown.to_string().contains("jf, other_keep"));
+    }
+
+    #[test]
+    fn invalid_profiles_fail_at_load() {
+        let error = Config::parse("[profiles.jf.fees]\nbroker_relations = 7")
+            .err()
+            .unwrap()
+            .to_string();
+
+        assert!(error.contains("profile \"jf\""));
+        assert!(error.contains("fees.broker_relations"));
+    }
+
+    #[test]
+    fn destinations_inherit_fees_and_freight() {
+        let config = Config::parse(
+            "[freight]
+            price_per_m3 = 1200.0
+
+            [[destinations]]
+            name = \"keepstar\"
+            location_id = 1030049082711
+
+            [[destinations]]
+            name = \"staging\"
+            location_id = 1022734985679
+            region_id = 10000060
+            broker_fee = 0.005
+
+            [destinations.freight]
+            price_per_m3 = 400.0
+            collateral_rate = 0.01",
+        )
+        .unwrap();
+
+        let destinations = config.destinations();
+
+        assert_eq!(destinations.len(), 2);
+        assert_eq!(destinations[0].freight.price_per_m3, 1200.0);
+        assert_eq!(destinations[0].broker_fee, 0.01);
+        assert_eq!(destinations[1].freight.price_per_m3, 400.0);
+        assert_eq!(destinations[1].sell_fee(), 0.005 + config.fees.sales_tax());
+        assert_eq!(
+            Config::default().destinations()[0].location_id,
+            StationsConfig::default().abroad_location_id
+        );
+
+        let duplicate = Config::parse(
+            "[[destinations]]
+            name = \"a\"
+            location_id = 1
+            sales_tax = 1.5
+
+            [[destinations]]
+            name = \"b\"
+            location_id = 1",
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(duplicate.contains("destinations[0].sales_tax must be at least 0 and below 1"));
+        assert!(duplicate.contains("destinations[1].location_id 1 is listed twice"));
+    }
+
+    #[test]
+    fn sources_default_to_jita_and_name_real_destinations() {
+        let jita = &Config::default().sources()[0];
+        assert_eq!(jita.location_id, StationsConfig::default().jita_station_id);
+        assert_eq!(jita.broker_fee, FeesConfig::default().jita_buy_fee(jita.location_id));
+
+        let error = Config::parse(
+            "[[sources]]
+            name = \"amarr\"
+            location_id = 60008494
+            freight.keepstar = { price_per_m3 = 900.0 }",
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(error.contains("sources[0].freight.keepstar is not a destination"));
+
+        let config = Config::parse(
+            "[[sources]]
+            name = \"amarr\"
+            location_id = 60008494
+            freight.abroad = { price_per_m3 = 900.0 }",
+        )
+        .unwrap();
+        let abroad = &config.destinations()[0];
+        assert_eq!(config.sources()[0].freight_to(abroad).price_per_m3, 900.0);
+        assert_eq!(config.market_locations().len(), 2);
+    }
+
+    #[test]
+    fn reversed_config_exports_to_jita() {
+        let config = Config::parse(
+            "[reverse.freight]
+            price_per_m3 = 300.0
+
+            [[destinations]]
+            name = \"keepstar\"
+            location_id = 1030049082711
+            region_id = 10000060",
+        )
+        .unwrap();
+
+        let reversed = config.reversed();
+        let sources = reversed.sources();
+        let destinations = reversed.destinations();
+
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].name, "keepstar");
+        assert_eq!(sources[0].broker_fee, config.destinations()[0].broker_fee);
+        assert_eq!(destinations.len(), 1);
+        assert_eq!(destinations[0].location_id, config.stations.jita_station_id);
+        assert_eq!(sources[0].freight_to(&destinations[0]).price_per_m3, 300.0);
+        assert_eq!(reversed.market_locations(), config.market_locations());
+    }
+}
@@ -1,30 +1,39 @@
 pub mod datagetter {
     use crate::from_str;
     use crate::goonmetrics::goonmetrics::*;
+    use crate::history::history::HistoryStats;
+    use crate::http::http::{HttpClient, RetryPolicy};
+    use crate::sde::sde::{missing_tables, Sde};
+    use chrono::{DateTime, TimeDelta, Utc};
     use error_chain::error_chain;
+    use futures::stream::{self, StreamExt};
     use reqwest;
-    use rusqlite::{Connection as SQL_Connection, Result as SQL_Result};
     use serde::{Deserialize, Serialize};
-    use std::path::Path;
-    use std::sync::mpsc;
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::PathBuf;
     use struct_field_names_as_array::FieldNamesAsSlice;
-    use tokio::task;
+    use std::time::{Duration, Instant};
 
     error_chain! {
         foreign_links {
             Io(std::io::Error);
             HttpRequest(reqwest::Error);
+            Json(serde_json::Error);
+            Sqlite(rusqlite::Error);
         }
     }
 
-    #[derive(Debug, PartialEq)]
-    pub struct ItemDataFromDb {
-        pub type_id: i32,
-        pub type_volume: f32,
+    /// Per-user directory for everything we write ourselves (tokens, caches).
+    pub fn get_app_data_dir() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("gescheftmacher")
     }
+
     #[derive(Debug, PartialEq, Clone, FieldNamesAsSlice, Deserialize, Serialize)]
     pub struct TradeData {
-        pub updated: String,
+        /// When the market last saw this quote, `None` if it did not say.
+        pub updated: Option<DateTime<Utc>>,
         pub weekly_movement: f64,
         pub buy_max: f64,
         pub buy_listed: i64,
@@ -37,186 +46,389 @@ pub mod datagetter {
         pub type_id: i32,
         pub type_volume: f32,
         pub type_name: String,
-        pub jita_trade_data: Option<TradeData>,
-        pub abroad_trade_data: Option<TradeData>,
+        pub market_group_id: Option<i32>,
+        pub metadata: ItemMetadata,
+        /// Every source and destination market, keyed by location ID.
+        pub markets: Markets,
     }
 
-    pub fn get_stored_type_data(
-        conn: &SQL_Connection,
-        type_name: &str,
-    ) -> SQL_Result<ItemDataFromDb> {
-        let mut stmt = conn.prepare(
-            "SELECT typeID, volume FROM invTypes
-            WHERE typeName = :type_name",
-        )?;
-        let mut rows = stmt.query(&[(":type_name", type_name)])?;
+    /// What one market knows about an item.
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct MarketData {
+        pub trade_data: Option<TradeData>,
+        pub history: Option<HistoryStats>,
+    }
 
-        let mut names: Vec<f32> = Vec::new();
-        while let Some(row) = rows.next()? {
-            names.push(row.get(0)?);
-            names.push(row.get(1)?);
-        }
-        let result = ItemDataFromDb {
-            type_id: names[0] as i32,
-            type_volume: names[1],
-        };
+    pub type Markets = BTreeMap<i64, MarketData>;
+
+    /// What kind of item this is. Every field is `None` when the SDE lacks the table it comes from.
+    #[derive(Debug, PartialEq, Clone, Default, FieldNamesAsSlice, Deserialize, Serialize)]
+    pub struct ItemMetadata {
+        pub group_name: Option<String>,
+        pub category_name: Option<String>,
+        /// "Tech II", "Faction", "Deadspace" etc. from `invMetaGroups`.
+        pub meta_group_name: Option<String>,
+        pub meta_level: Option<i32>,
+        pub tech_level: Option<i32>,
+    }
 
-        Ok(result)
+    pub const META_LEVEL_ATTRIBUTE_ID: i32 = 633;
+    pub const TECH_LEVEL_ATTRIBUTE_ID: i32 = 422;
+
+    /// Narrows items by their metadata. Empty lists and `None` bounds let everything through,
+    /// names are compared case-insensitively.
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct ItemMetadataFilter {
+        pub categories: Vec<String>,
+        pub groups: Vec<String>,
+        pub meta_groups: Vec<String>,
+        pub tech_levels: Vec<i32>,
+        pub min_meta_level: Option<i32>,
+        pub max_meta_level: Option<i32>,
     }
 
-    pub fn get_tradable_item_names(conn: &SQL_Connection) -> SQL_Result<Vec<String>> {
-        let mut stmt = conn.prepare(
-            "SELECT typeName FROM invTypes
-         WHERE marketGroupID IS NOT NULL AND description <> ''",
-        )?;
+    impl ItemMetadataFilter {
+        /// Base items often have no meta or tech level attribute, they count as meta 0 and tech 1.
+        pub fn matches(&self, metadata: &ItemMetadata) -> bool {
+            let name_matches = |names: &Vec<String>, name: &Option<String>| {
+                names.is_empty()
+                    || name
+                        .as_ref()
+                        .is_some_and(|name| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+            };
+            let meta_level = metadata.meta_level.unwrap_or(0);
+            let tech_level = metadata.tech_level.unwrap_or(1);
+            name_matches(&self.categories, &metadata.category_name)
+                && name_matches(&self.groups, &metadata.group_name)
+                && name_matches(&self.meta_groups, &metadata.meta_group_name)
+                && (self.tech_levels.is_empty() || self.tech_levels.contains(&tech_level))
+                && self.min_meta_level.is_none_or(|min| meta_level >= min)
+                && self.max_meta_level.is_none_or(|max| meta_level <= max)
+        }
+    }
 
-        let mut rows = stmt.query([])?;
+    impl TradeData {
+        pub fn age(&self, now: DateTime<Utc>) -> Option<TimeDelta> {
+            return self.updated.map(|updated| now - updated);
+        }
 
-        let mut names: Vec<String> = Vec::new();
-        while let Some(row) = rows.next()? {
-            names.push(row.get(0)?);
+        /// A quote without a timestamp counts as stale, there is no telling how old it is.
+        pub fn is_stale(&self, max_age: TimeDelta, now: DateTime<Utc>) -> bool {
+            return self.age(now).is_none_or(|age| age > max_age);
+        }
+    }
+
+    impl ItemData {
+        pub fn trade_data_at(&self, location_id: i64) -> Option<&TradeData> {
+            self.markets.get(&location_id)?.trade_data.as_ref()
+        }
+
+        pub fn history_at(&self, location_id: i64) -> Option<&HistoryStats> {
+            self.markets.get(&location_id)?.history.as_ref()
         }
 
-        Ok(names)
+        /// Any market quoting a price older than `max_age`.
+        pub fn has_stale_quote(&self, max_age: TimeDelta, now: DateTime<Utc>) -> bool {
+            return self
+                .markets
+                .values()
+                .filter_map(|market| market.trade_data.as_ref())
+                .any(|trade_data| trade_data.is_stale(max_age, now));
+        }
     }
 
-    pub fn get_tradable_item_names_from_db() -> Vec<String> {
-        // Assume we have a connection to the SQLite database file (eve.db)
-        let db_path = Path::new("src").join("eve.db");
-        let src_path_connection = SQL_Connection::open(db_path);
+    /// Items keyed by type ID, in type ID order. Type names are not unique in the
+    /// SDE, so the ID is the only safe key.
+    pub type ItemDataByType = BTreeMap<i32, ItemData>;
 
-        match src_path_connection {
-            Ok(connection) => {
-                // Execute a SELECT query on the 'tradable_items' table
-                let tradable_item_names = get_tradable_item_names(&connection).unwrap();
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum SdeItemSelection {
+        /// Everything with a market group and a description.
+        Tradable,
+        /// Exactly these types; unknown IDs are an error.
+        TypeIds(Vec<i32>),
+    }
 
-                // Return the vector of item names
-                tradable_item_names
-            }
-            Err(_e) => {
-                // If an error occurs while establishing a connection, return an empty vector
-                vec![]
+    /// The metadata columns of the item query, each one `NULL` when its tables are not in the dump.
+    fn metadata_columns(sde: &Sde) -> Result<(String, String)> {
+        let has = |tables: &[&str]| -> Result<bool> {
+            Ok(missing_tables(sde.conn(), tables)?.is_empty())
+        };
+        let mut columns = vec![];
+        let mut joins = vec![];
+        if has(&["invGroups"])? {
+            columns.push("g.groupName".to_owned());
+            joins.push("LEFT JOIN invGroups g ON g.groupID = t.groupID");
+            if has(&["invCategories"])? {
+                columns.push("c.categoryName".to_owned());
+                joins.push("LEFT JOIN invCategories c ON c.categoryID = g.categoryID");
+            } else {
+                columns.push("NULL".to_owned());
             }
+        } else {
+            columns.extend(["NULL".to_owned(), "NULL".to_owned()]);
         }
+        if has(&["invMetaTypes", "invMetaGroups"])? {
+            columns.push("mg.metaGroupName".to_owned());
+            joins.push(
+                "LEFT JOIN invMetaTypes mt ON mt.typeID = t.typeID
+                LEFT JOIN invMetaGroups mg ON mg.metaGroupID = mt.metaGroupID",
+            );
+        } else {
+            columns.push("NULL".to_owned());
+        }
+        for attribute_id in [META_LEVEL_ATTRIBUTE_ID, TECH_LEVEL_ATTRIBUTE_ID] {
+            columns.push(if has(&["dgmTypeAttributes"])? {
+                format!(
+                    "(SELECT CAST(COALESCE(a.valueInt, a.valueFloat) AS INTEGER)
+                    FROM dgmTypeAttributes a WHERE a.typeID = t.typeID AND a.attributeID = {})",
+                    attribute_id
+                )
+            } else {
+                "NULL".to_owned()
+            });
+        }
+        Ok((columns.join(", "), joins.join("\n")))
     }
 
-    pub fn get_stored_type_volume_packed(conn: &SQL_Connection, type_id: i32) -> SQL_Result<f32> {
-        let mut stmt = conn.prepare(
-            "
-             select volume from invVolumes
-             where typeID = ?1
-            ",
-        )?;
-
-        let mut rows = stmt.query(rusqlite::params![type_id])?;
+    /// Loads the selected items with one joined query. The packed volume from
+    /// `invVolumes` wins over the assembled `invTypes.volume` when there is one.
+    pub fn get_item_data_from_db(
+        sde: &Sde,
+        selection: &SdeItemSelection,
+    ) -> Result<ItemDataByType> {
+        let (metadata_columns, metadata_joins) = metadata_columns(sde)?;
+        let select = format!(
+            "SELECT t.typeID, t.typeName, t.volume, v.volume, t.marketGroupID, {}
+            FROM invTypes t
+            LEFT JOIN invVolumes v ON v.typeID = t.typeID
+            {}",
+            metadata_columns, metadata_joins
+        );
+        let (query, ids_json) = match selection {
+            SdeItemSelection::Tradable => (
+                format!(
+                    "{} WHERE t.marketGroupID IS NOT NULL AND t.description <> ''",
+                    select
+                ),
+                None,
+            ),
+            SdeItemSelection::TypeIds(type_ids) => (
+                format!(
+                    "{} WHERE t.typeID IN (SELECT value FROM json_each(?1))",
+                    select
+                ),
+                Some(serde_json::to_string(type_ids)?),
+            ),
+        };
 
-        let mut res = Vec::new();
+        let mut stmt = sde.conn().prepare(&query)?;
+        let mut rows = match &ids_json {
+            Some(ids_json) => stmt.query([ids_json])?,
+            None => stmt.query([])?,
+        };
+        let mut result = ItemDataByType::new();
         while let Some(row) = rows.next()? {
-            res.push(row.get(0)?);
+            let type_id: i32 = row.get(0)?;
+            let type_volume: Option<f32> = row.get(2)?;
+            let packed_volume: Option<f32> = row.get(3)?;
+            result.entry(type_id).or_insert(ItemData {
+                type_id,
+                type_name: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                type_volume: packed_volume.or(type_volume).unwrap_or(0.0),
+                market_group_id: row.get(4)?,
+                metadata: ItemMetadata {
+                    group_name: row.get(5)?,
+                    category_name: row.get(6)?,
+                    meta_group_name: row.get(7)?,
+                    meta_level: row.get(8)?,
+                    tech_level: row.get(9)?,
+                },
+                markets: Markets::new(),
+            });
         }
 
-        match res.len() {
-            0 => Err(rusqlite::Error::InvalidQuery),
-            _ => Ok(res[0]),
+        if let SdeItemSelection::TypeIds(type_ids) = selection {
+            let unknown: Vec<String> = type_ids
+                .iter()
+                .filter(|id| !result.contains_key(id))
+                .map(|id| id.to_string())
+                .collect();
+            if !unknown.is_empty() {
+                return Err(format!(
+                    "{} has no type IDs {}",
+                    sde.describe(),
+                    unknown.join(", ")
+                )
+                .into());
+            }
+        } else if result.is_empty() {
+            return Err(format!("{} lists no tradable items", sde.describe()).into());
         }
+        Ok(result)
     }
 
-    pub fn get_item_data_from_db(names: Vec<String>) -> Vec<ItemData> {
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        println!("PATH:\n{:?}", db_path);
-        let src_path_connection = SQL_Connection::open(db_path);
+    const MAX_GOONMETRICS_ID_QUANTITY: usize = 99;
+    pub const GOONMETRICS_BASE_URL: &str = "https://goonmetrics.apps.goonswarm.org";
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FetchOptions {
+        /// How many batch requests may be in flight at the same time.
+        pub max_concurrent_requests: usize,
+        pub request_timeout: Duration,
+        pub retry: RetryPolicy,
+    }
 
-        let exe = std::env::current_exe().unwrap();
-        let exe_loc = exe.parent().unwrap();
-        let exe_path = Path::new(&exe_loc).join("eve.db");
-        let eve_db: SQL_Connection;
+    impl Default for FetchOptions {
+        fn default() -> Self {
+            FetchOptions {
+                max_concurrent_requests: 8,
+                request_timeout: Duration::from_secs(30),
+                retry: RetryPolicy::default(),
+            }
+        }
+    }
 
-        if let Err(_err) = src_path_connection {
-            eve_db = SQL_Connection::open(exe_path.clone()).unwrap()
-        } else {
-            eve_db = src_path_connection.unwrap()
+    async fn fetch_price_data_batch(
+        http: &HttpClient,
+        base_url: &str,
+        station_id: &str,
+        item_id_batch: &[i32],
+    ) -> Result<PriceData> {
+        let item_ids = item_id_batch
+            .iter()
+            .map(|id| id.to_string() + ",")
+            .collect::<String>();
+
+        let url = format!(
+            "{base_url}/api/price_data/?station_id={station_id}&type_id={item_ids}"
+        );
+
+        http.fetch(
+            |client| client.get(&url),
+            |_, body| {
+                let data: Goonmetrics =
+                    from_str(body).map_err(|e| format!("bad goonmetrics xml: {}", e))?;
+                Ok(data.price_data)
+            },
+        )
+        .await
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FailedBatch {
+        pub type_ids: Vec<i32>,
+        pub cause: String,
+    }
+
+    /// Outcome of a fetch that keeps going past failed batches.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FetchReport<T> {
+        pub data: T,
+        pub failed_batches: Vec<FailedBatch>,
+        pub elapsed: Duration,
+    }
+
+    impl<T> FetchReport<T> {
+        pub fn is_complete(&self) -> bool {
+            self.failed_batches.is_empty()
         }
-        println!("exe_path:\n{:?}", exe_path);
 
-        names
-            .into_iter()
-            .map(|name| {
-                let stored = get_stored_type_data(&eve_db, &name).unwrap();
+        pub fn failed_type_ids(&self) -> Vec<i32> {
+            self.failed_batches
+                .iter()
+                .flat_map(|batch| batch.type_ids.iter().cloned())
+                .collect()
+        }
 
-                let item_id = stored.type_id;
-                let packed_volume = get_stored_type_volume_packed(&eve_db, item_id);
+        pub fn missing_type_count(&self) -> usize {
+            self.failed_batches
+                .iter()
+                .map(|batch| batch.type_ids.len())
+                .sum()
+        }
 
-                let volume: f32;
-                if let Err(_err) = packed_volume {
-                    volume = stored.type_volume;
-                } else {
-                    volume = packed_volume.unwrap();
-                }
+        pub fn summary(&self) -> String {
+            if self.is_complete() {
+                return format!("all prices fetched in {:.1}s", self.elapsed.as_secs_f64());
+            }
+            format!(
+                "{} items missing prices ({} failed batches) after {:.1}s",
+                self.missing_type_count(),
+                self.failed_batches.len(),
+                self.elapsed.as_secs_f64()
+            )
+        }
 
-                let result = ItemData {
-                    type_name: name.to_string(),
-                    type_id: item_id,
-                    type_volume: volume,
-                    jita_trade_data: None,
-                    abroad_trade_data: None,
-                };
-                return result;
-            })
-            .collect()
+        /// Strict view for callers that cannot use partial data.
+        pub fn into_result(self) -> Result<T> {
+            match self.failed_batches.first() {
+                None => Ok(self.data),
+                Some(batch) => Err(format!(
+                    "{} batches ({} items) failed, first cause: {}",
+                    self.failed_batches.len(),
+                    self.missing_type_count(),
+                    batch.cause
+                )
+                .into()),
+            }
+        }
     }
 
-    const MAX_GOONMETRICS_ID_QUANTITY: usize = 99;
+    impl FetchReport<TradeDataByType> {
+        /// Folds in a retry of this report's failed batches.
+        pub fn merge_retry(&mut self, retry: FetchReport<TradeDataByType>) {
+            self.data.extend(retry.data);
+            self.failed_batches = retry.failed_batches;
+            self.elapsed += retry.elapsed;
+        }
+    }
 
+    /// Fetches goonmetrics prices in batches of 99 IDs, at most
+    /// `max_concurrent_requests` batches at a time. Returns one `PriceData` per
+    /// successful batch; failed batches are reported with their cause instead of aborting.
     pub async fn get_item_data_from_api(
+        http: &HttpClient,
+        base_url: &str,
         station_id: &str,
         item_ids: &Vec<i32>,
-    ) -> Result<Vec<PriceData>> {
+        max_concurrent_requests: usize,
+    ) -> FetchReport<Vec<PriceData>> {
+        let started = Instant::now();
         let item_id_batches = split_large_id_bulks(item_ids, MAX_GOONMETRICS_ID_QUANTITY);
 
-        let (tx, rx) = mpsc::channel();
-
-        for item_id_batch in item_id_batches {
-            let transmitter = tx.clone();
-            let st_id = station_id.to_owned().clone();
-            task::spawn(async move {
-                let item_ids = &item_id_batch
-                    .into_iter()
-                    .map(|id| id.to_string() + ",")
-                    .collect::<String>();
-
-                let jita_url = format!(
-                    "https://goonmetrics.\
-                    apps.goonswarm.org/api/price_data/\
-                    ?station_id={st_id}&type_id={item_ids}"
-                );
-
-                async fn fetcher(url: &str) -> Result<PriceData> {
-                    let res = reqwest::get(url).await?;
-                    let body = res.text().await?;
-
-                    let data: Goonmetrics = from_str(&body).unwrap();
-                    let pd = data.price_data;
-                    return Ok(pd);
+        let results: Vec<(Vec<i32>, Result<PriceData>)> = stream::iter(item_id_batches)
+            .map(|item_id_batch| {
+                let http = http.clone();
+                let base_url = base_url.to_owned();
+                let station_id = station_id.to_owned();
+                async move {
+                    let result =
+                        fetch_price_data_batch(&http, &base_url, &station_id, &item_id_batch)
+                            .await;
+                    (item_id_batch, result)
                 }
-
-                let data: PriceData = fetcher(&jita_url).await.unwrap();
-                transmitter.send(data).unwrap();
             })
-            .await
-            .unwrap();
-        }
-        drop(tx);
+            .buffered(max_concurrent_requests.max(1))
+            .collect()
+            .await;
 
-        let mut result = vec![];
-        for res in rx {
-            for data in res.types {
-                result.push(data);
+        let mut report = FetchReport {
+            data: vec![],
+            failed_batches: vec![],
+            elapsed: Duration::ZERO,
+        };
+        for (item_id_batch, result) in results {
+            match result {
+                Ok(price_data) => report.data.push(price_data),
+                Err(e) => report.failed_batches.push(FailedBatch {
+                    type_ids: item_id_batch,
+                    cause: e.to_string(),
+                }),
             }
         }
-
-        let data = PriceData { types: result };
-        return Ok(vec![data]);
+        report.elapsed = started.elapsed();
+        return report;
     }
 
     pub fn split_large_id_bulks(item_ids: &Vec<i32>, split_treshold: usize) -> Vec<Vec<i32>> {
@@ -241,91 +453,78 @@ pub mod datagetter {
         }
     }
 
+    pub type TradeDataByType = HashMap<i32, TradeData>;
+
+    /// Values goonmetrics left empty count as nothing listed or traded.
+    impl From<&ItemType> for TradeData {
+        fn from(item_type: &ItemType) -> Self {
+            TradeData {
+                updated: item_type.updated,
+                weekly_movement: item_type.all.weekly_movement.unwrap_or(0.0),
+                sell_listed: item_type.sell.listed.unwrap_or(0),
+                sell_min: item_type.sell.min.unwrap_or(0.0),
+                buy_listed: item_type.buy.listed.unwrap_or(0),
+                buy_max: item_type.buy.max.unwrap_or(0.0),
+            }
+        }
+    }
+
+    /// Normalizes goonmetrics responses into `TradeData` keyed by type ID. The index is
+    /// built once per market so the merge is a lookup per item. A type reported by
+    /// several chunks keeps its newest `updated`; on a tie the first chunk wins, so the
+    /// result does not depend on which batch finished first.
+    pub fn trade_data_by_type(price_data: &[PriceData]) -> TradeDataByType {
+        let mut result: TradeDataByType = HashMap::new();
+        for chunk in price_data {
+            for Types::Type(item_type) in &chunk.types {
+                insert_newest(&mut result, item_type.id, TradeData::from(item_type));
+            }
+        }
+        return result;
+    }
+
+    /// A quote without `updated` loses against any dated one.
+    pub fn insert_newest(trade_data: &mut TradeDataByType, type_id: i32, candidate: TradeData) {
+        match trade_data.get(&type_id) {
+            Some(existing) if existing.updated >= candidate.updated => {}
+            _ => {
+                trade_data.insert(type_id, candidate);
+            }
+        }
+    }
+
+    /// Pairs every item with the prices of every market, keyed by location ID. A market
+    /// that did not report an item leaves `None`; items no market knows are dropped.
     pub fn merge_trade_data(
         items_data: &Vec<ItemData>,
-        jita_trade_data: &Vec<PriceData>,
-        abroad_trade_data: &Vec<PriceData>,
+        trade_data: &BTreeMap<i64, TradeDataByType>,
     ) -> Vec<ItemData> {
         let result: Vec<_> = items_data
             .into_iter()
-            .map(|item| {
-                let mut enriched_item = ItemData {
-                    type_name: item.type_name.clone(),
-                    type_id: item.type_id,
-                    type_volume: item.type_volume,
-                    jita_trade_data: None,
-                    abroad_trade_data: None,
-                };
+            .filter_map(|item| {
                 let id = item.type_id;
-                let jt = &jita_trade_data[0].types;
-
-                let item_jita_trade_data = jt.into_iter().find(|jtd| match jtd {
-                    Types::Type(item_type) => {
-                        return item_type.id == id;
-                    }
-                });
-
-                match item_jita_trade_data {
-                    Some(&Types::Type(ref item_type)) => {
-                        enriched_item.jita_trade_data = Some(TradeData {
-                            updated: item_type.updated.clone(),
-                            weekly_movement: item_type
-                                .all
-                                .weekly_movement
-                                .parse::<f64>()
-                                .expect("Fail to parse"),
-                            sell_listed: item_type
-                                .sell
-                                .listed
-                                .parse::<i64>()
-                                .expect("Fail to parse"),
-                            sell_min: item_type.sell.min.parse::<f64>().expect("Fail to parse"),
-                            buy_listed: item_type.buy.listed.parse::<i64>().expect("Fail to parse"),
-                            buy_max: item_type.buy.max.parse::<f64>().expect("Fail to parse"),
-                        })
-                    }
-                    _ => {
-                        let en_item_jita_t_d = enriched_item.jita_trade_data;
-                        panic!(
-                            "fail to compare\n
-                    JITA TRADE DATA:\n {:?}\n
-                    ITEM FROM DB DATA:\n {:?}
-                    ",
-                            item_jita_trade_data, en_item_jita_t_d
-                        )
-                    }
+                let markets: Markets = trade_data
+                    .iter()
+                    .map(|(location_id, trade_data)| {
+                        let market = MarketData {
+                            trade_data: trade_data.get(&id).cloned(),
+                            history: item.history_at(*location_id).cloned(),
+                        };
+                        (*location_id, market)
+                    })
+                    .collect();
+                if markets.values().all(|market| market.trade_data.is_none()) {
+                    return None;
                 }
 
-                let at = &abroad_trade_data[0].types;
-                let item_abroad_trade_data = at.into_iter().find(|atd| match atd {
-                    Types::Type(item_type) => {
-                        return item_type.id == id;
-                    }
+                return Some(ItemData {
+                    type_name: item.type_name.clone(),
+                    type_id: item.type_id,
+                    type_volume: item.type_volume,
+                    markets,
+                    market_group_id: item.market_group_id,
+                    metadata: item.metadata.clone(),
                 });
-
-                match item_abroad_trade_data {
-                    Some(&Types::Type(ref item_type)) => {
-                        enriched_item.abroad_trade_data = Some(TradeData {
-                            updated: item_type.updated.clone(),
-                            weekly_movement: item_type
-                                .all
-                                .weekly_movement
-                                .parse::<f64>()
-                                .expect("Fail to parse"),
-                            sell_listed: item_type
-                                .sell
-                                .listed
-                                .parse::<i64>()
-                                .expect("Fail to parse"),
-                            sell_min: item_type.sell.min.parse::<f64>().expect("Fail to parse"),
-                            buy_listed: item_type.buy.listed.parse::<i64>().expect("Fail to parse"),
-                            buy_max: item_type.buy.max.parse::<f64>().expect("Fail to parse"),
-                        })
-                    }
-                    _ => panic!("Terrible wrong shit"),
-                }
-
-                return enriched_item;
             })
             .collect();
 
@@ -333,12 +532,30 @@ pub mod datagetter {
     }
 }
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
     use crate::datagetter::datagetter::*;
+    use crate::config::config::StationsConfig;
+    use crate::sde::sde::Sde;
+    use crate::sde::tests::temp_sde;
     use rusqlite::Connection as SQL_Connection;
+    use std::collections::BTreeMap;
     use std::path::Path;
 
+    /// Quotes at the source and destination of a config without `[[sources]]` and
+    /// `[[destinations]]`.
+    pub fn markets(jita: Option<TradeData>, abroad: Option<TradeData>) -> Markets {
+        let stations = StationsConfig::default();
+        let market = |trade_data| MarketData {
+            trade_data,
+            history: None,
+        };
+        Markets::from([
+            (stations.jita_station_id, market(jita)),
+            (stations.abroad_location_id, market(abroad)),
+        ])
+    }
+
     #[test]
     fn test_split_by_treshold_small() {
         let treshold: usize = 3;
@@ -366,55 +583,414 @@ mod tests {
     }
 
     #[test]
-    fn get_item_from_db_by_name() {
-        let name = "Hulk";
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        println!("PATH:\n{:?}", db_path);
-        let src_path_connection = SQL_Connection::open(db_path);
-
-        let exe = std::env::current_exe().unwrap();
-        let exe_loc = exe.parent().unwrap();
-        let exe_path = Path::new(&exe_loc).join("eve.db");
-        let eve_db: SQL_Connection;
-
-        if let Err(_err) = src_path_connection {
-            eve_db = SQL_Connection::open(exe_path.clone()).unwrap()
-        } else {
-            eve_db = src_path_connection.unwrap()
-        }
-        let stored = get_stored_type_data(&eve_db, name).unwrap();
-        println!("aaaa:\n{:?}", stored);
+    fn trade_data_by_type_normalizes_all_chunks() {
+        use crate::goonmetrics::goonmetrics::*;
+        let item_type = |id: i32, sell_min: f64| {
+            Types::Type(ItemType {
+                id,
+                updated: "2024-05-03T13:36:22Z".parse().ok(),
+                all: All {
+                    weekly_movement: Some(12.5),
+                },
+                buy: Buy {
+                    listed: Some(4),
+                    max: Some(2.0),
+                },
+                sell: Sell {
+                    listed: Some(7),
+                    min: Some(sell_min),
+                },
+            })
+        };
+        let chunks = vec![
+            PriceData {
+                types: vec![item_type(34, 3.0)],
+            },
+            PriceData {
+                types: vec![item_type(35, 9.0)],
+            },
+        ];
+
+        let result = trade_data_by_type(&chunks);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[&34].sell_min, 3.0);
+        assert_eq!(result[&35].sell_min, 9.0);
+        assert_eq!(result[&35].weekly_movement, 12.5);
+        assert_eq!(result[&35].buy_listed, 4);
+    }
+
+    #[test]
+    fn duplicate_types_keep_newest_update() {
+        use crate::goonmetrics::goonmetrics::*;
+        let item_type = |updated: &str, sell_min: f64| {
+            Types::Type(ItemType {
+                id: 34,
+                updated: updated.parse().ok(),
+                all: All {
+                    weekly_movement: Some(1.0),
+                },
+                buy: Buy {
+                    listed: Some(1),
+                    max: Some(1.0),
+                },
+                sell: Sell {
+                    listed: Some(1),
+                    min: Some(sell_min),
+                },
+            })
+        };
+        let older = PriceData {
+            types: vec![item_type("2024-05-03T13:36:22Z", 3.0)],
+        };
+        let newer = PriceData {
+            types: vec![item_type("2024-05-03T14:00:00Z", 4.0)],
+        };
+        let same_age = PriceData {
+            types: vec![item_type("2024-05-03T14:00:00Z", 5.0)],
+        };
+
+        let forward = trade_data_by_type(&[older.clone(), newer.clone(), same_age.clone()]);
+        let backward = trade_data_by_type(&[same_age, newer.clone(), older]);
+
+        assert_eq!(forward[&34].sell_min, 4.0);
+        assert_eq!(backward[&34].sell_min, 5.0);
         assert_eq!(
-            stored,
-            ItemDataFromDb {
-                type_id: 22544,
-                type_volume: 150000.0
-            }
+            trade_data_by_type(&[newer])[&34].updated,
+            "2024-05-03T14:00:00Z".parse().ok()
+        );
+    }
+
+    pub const GOONMETRICS_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<goonmetrics><price_data version="2"><type id="34"><updated>2024-05-03T13:36:22Z</updated><all><weekly_movement>1000.5</weekly_movement></all><buy><listed>300</listed><max>4.5</max></buy><sell><listed>200</listed><min>5.1</min></sell></type></price_data></goonmetrics>"#;
+
+    #[test]
+    fn merge_keeps_items_missing_one_side() {
+        let item = |type_id| ItemData {
+            type_id,
+            type_volume: 1.0,
+            type_name: type_id.to_string(),
+            markets: Markets::new(),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let trade_data = TradeData {
+            updated: "2024-05-03T13:36:22Z".parse().ok(),
+            weekly_movement: 1.0,
+            buy_max: 1.0,
+            buy_listed: 1,
+            sell_min: 1.0,
+            sell_listed: 1,
+        };
+        let jita: TradeDataByType = [(34, trade_data.clone()), (35, trade_data.clone())]
+            .into_iter()
+            .collect();
+        let keep: TradeDataByType = [(35, trade_data.clone())].into_iter().collect();
+        let staging: TradeDataByType = [(36, trade_data.clone())].into_iter().collect();
+        let markets = BTreeMap::from([(60003760, jita), (1, keep), (2, staging)]);
+
+        let merged = merge_trade_data(&vec![item(34), item(35), item(36), item(37)], &markets);
+
+        let ids: Vec<i32> = merged.iter().map(|item| item.type_id).collect();
+        assert_eq!(ids, vec![34, 35, 36]);
+        assert_eq!(merged[0].trade_data_at(1), None);
+        assert_eq!(merged[1].trade_data_at(1), Some(&trade_data));
+        assert_eq!(merged[1].trade_data_at(2), None);
+        assert_eq!(merged[2].trade_data_at(2), Some(&trade_data));
+        assert_eq!(merged[2].trade_data_at(60003760), None);
+        assert_eq!(merged[2].markets.len(), 3);
+    }
+
+    #[test]
+    fn stale_quotes_are_detected() {
+        use chrono::{TimeDelta, Utc};
+        let now = Utc::now();
+        let quote = |age_minutes: Option<i64>| TradeData {
+            updated: age_minutes.map(|minutes| now - TimeDelta::minutes(minutes)),
+            weekly_movement: 1.0,
+            buy_max: 1.0,
+            buy_listed: 1,
+            sell_min: 1.0,
+            sell_listed: 1,
+        };
+        let max_age = TimeDelta::hours(3);
+        let mut item = ItemData {
+            type_id: 34,
+            type_volume: 0.01,
+            type_name: "Tritanium".to_owned(),
+            markets: Markets::new(),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let at = |trade_data| MarketData {
+            trade_data: Some(trade_data),
+            history: None,
+        };
+
+        item.markets.insert(60003760, at(quote(Some(42))));
+        assert!(!item.has_stale_quote(max_age, now));
+        item.markets.insert(1, at(quote(Some(42))));
+        item.markets.insert(2, at(quote(Some(3 * 24 * 60))));
+        assert!(item.has_stale_quote(max_age, now));
+        item.markets.insert(2, at(quote(None)));
+        assert!(item.has_stale_quote(max_age, now));
+        assert_eq!(quote(Some(42)).age(now), Some(TimeDelta::minutes(42)));
+    }
+
+    #[tokio::test]
+    async fn batches_are_fetched_concurrently() {
+        use std::time::{Duration, Instant};
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/price_data/"))
+            .and(query_param("station_id", "60003760"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(GOONMETRICS_XML)
+                    .set_delay(Duration::from_millis(300)),
+            )
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let item_ids: Vec<i32> = (1..=250).collect();
+        let http = crate::http::tests::fast_retry_client(0);
+        let started = Instant::now();
+        let result = get_item_data_from_api(&http, &server.uri(), "60003760", &item_ids, 3)
+            .await;
+
+        assert!(result.is_complete());
+        assert_eq!(result.data.len(), 3);
+        assert!(started.elapsed() < Duration::from_millis(850));
+    }
+
+    #[tokio::test]
+    async fn slow_batches_time_out() {
+        use crate::http::http::{HttpClient, RateLimiter, RetryPolicy};
+        use std::sync::Arc;
+        use std::time::Duration;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(GOONMETRICS_XML)
+                    .set_delay(Duration::from_secs(3)),
+            )
+            .mount(&server)
+            .await;
+
+        let options = FetchOptions {
+            max_concurrent_requests: 1,
+            request_timeout: Duration::from_millis(200),
+            retry: RetryPolicy {
+                max_retries: 1,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+        };
+        let http = HttpClient::new(&options, Arc::new(RateLimiter::unlimited())).unwrap();
+        let result = get_item_data_from_api(&http, &server.uri(), "60003760", &vec![34], 1).await;
+
+        assert!(result.data.is_empty());
+        assert_eq!(result.failed_type_ids(), vec![34]);
+    }
+
+    #[tokio::test]
+    async fn failed_batches_are_reported_not_fatal() {
+        use wiremock::matchers::{method, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        // second batch of 100..=198 starts with type 100
+        Mock::given(method("GET"))
+            .and(query_param("type_id", format!("{},", (100..=198).map(|id: i32| id.to_string()).collect::<Vec<_>>().join(","))))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(GOONMETRICS_XML))
+            .mount(&server)
+            .await;
+
+        let item_ids: Vec<i32> = (1..=250).collect();
+        let http = crate::http::tests::fast_retry_client(0);
+        let report = get_item_data_from_api(&http, &server.uri(), "60003760", &item_ids, 2).await;
+
+        assert!(!report.is_complete());
+        assert_eq!(report.data.len(), 2);
+        assert_eq!(report.missing_type_count(), 99);
+        assert_eq!(report.failed_type_ids()[0], 100);
+        assert!(report.failed_batches[0].cause.contains("404"));
+        assert!(report.summary().starts_with("99 items missing prices (1 failed batches)"));
+        assert!(report.into_result().is_err());
+    }
+
+    fn eve_db() -> Sde {
+        Sde::open(&Path::new("src").join("eve.db")).unwrap()
+    }
+
+    fn find_by_name<'a>(items: &'a ItemDataByType, name: &str) -> &'a ItemData {
+        items
+            .values()
+            .find(|item| item.type_name == name)
+            .unwrap()
+    }
+
+    /// Hulk has a packed volume, Tritanium does not, and two types share a name.
+    fn fixture_sde(name: &str) -> Sde {
+        let path = temp_sde(name, &["invTypes", "invVolumes"]);
+        let conn = SQL_Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO invTypes VALUES (34, 'Tritanium', 0.01, 1857, 'Mineral');
+            INSERT INTO invTypes VALUES (22544, 'Hulk', 150000, 1, 'Exhumer');
+            INSERT INTO invTypes VALUES (900, 'Twin', 1, 2, 'First');
+            INSERT INTO invTypes VALUES (901, 'Twin', 2, 2, 'Second');
+            INSERT INTO invTypes VALUES (902, 'Unpublished', 1, NULL, '');
+            INSERT INTO invVolumes VALUES (22544, 3750);",
         )
+        .unwrap();
+        Sde::open(&path).unwrap()
     }
 
     #[test]
-    fn test_get_tradable_item_names_some_results() {
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        let src_path_connection = SQL_Connection::open(db_path);
+    fn tradable_items_come_from_one_joined_query() {
+        let items = get_item_data_from_db(&fixture_sde("joined"), &SdeItemSelection::Tradable)
+            .unwrap();
 
-        let result = get_tradable_item_names(&src_path_connection.unwrap()).unwrap();
+        let ids: Vec<i32> = items.keys().cloned().collect();
+        assert_eq!(ids, vec![34, 900, 901, 22544]);
+        assert_eq!(items[&22544].type_volume, 3750.0);
+        assert_eq!(items[&34].type_volume, 0.01);
+        assert_eq!(items[&900].type_name, items[&901].type_name);
+        assert_eq!(items[&901].type_volume, 2.0);
+    }
+
+    #[test]
+    fn metadata_comes_from_optional_tables() {
+        let path = temp_sde(
+            "metadata",
+            &[
+                "invTypes",
+                "invVolumes",
+                "invGroups",
+                "invCategories",
+                "invMetaTypes",
+                "invMetaGroups",
+                "dgmTypeAttributes",
+            ],
+        );
+        let conn = SQL_Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "ALTER TABLE invTypes ADD COLUMN groupID INTEGER;
+            INSERT INTO invTypes VALUES (2281, 'Adaptive Invulnerability Field II', 5, 9, 'T2', 77);
+            INSERT INTO invTypes VALUES (34, 'Tritanium', 0.01, 1857, 'Mineral', 18);
+            INSERT INTO invGroups VALUES (77, 7, 'Shield Hardener');
+            INSERT INTO invCategories VALUES (7, 'Module');
+            INSERT INTO invMetaTypes VALUES (2281, 2293, 2);
+            INSERT INTO invMetaGroups VALUES (2, 'Tech II');
+            INSERT INTO dgmTypeAttributes VALUES (2281, 633, NULL, 5.0);
+            INSERT INTO dgmTypeAttributes VALUES (2281, 422, 2, NULL);",
+        )
+        .unwrap();
+        let items = get_item_data_from_db(&Sde::open(&path).unwrap(), &SdeItemSelection::Tradable)
+            .unwrap();
+
+        assert_eq!(
+            items[&2281].metadata,
+            ItemMetadata {
+                group_name: Some("Shield Hardener".to_owned()),
+                category_name: Some("Module".to_owned()),
+                meta_group_name: Some("Tech II".to_owned()),
+                meta_level: Some(5),
+                tech_level: Some(2),
+            }
+        );
+        assert_eq!(items[&34].metadata.category_name, None);
+
+        // The minimal fixture has none of those tables.
+        let items = get_item_data_from_db(&fixture_sde("no-metadata"), &SdeItemSelection::Tradable)
+            .unwrap();
+        assert_eq!(items[&34].metadata, ItemMetadata::default());
+    }
+
+    #[test]
+    fn metadata_filter_treats_missing_levels_as_base_items() {
+        let t2_module = ItemMetadata {
+            group_name: Some("Shield Hardener".to_owned()),
+            category_name: Some("Module".to_owned()),
+            meta_group_name: Some("Tech II".to_owned()),
+            meta_level: Some(5),
+            tech_level: Some(2),
+        };
+        let mineral = ItemMetadata {
+            category_name: Some("Material".to_owned()),
+            ..ItemMetadata::default()
+        };
+        let filter = |filter: ItemMetadataFilter| {
+            [&t2_module, &mineral].map(|metadata| filter.matches(metadata))
+        };
+
+        assert_eq!(filter(ItemMetadataFilter::default()), [true, true]);
+        let modules = ItemMetadataFilter {
+            categories: vec!["module".to_owned()],
+            ..ItemMetadataFilter::default()
+        };
+        assert_eq!(filter(modules), [true, false]);
+        let tech_one = ItemMetadataFilter {
+            tech_levels: vec![1],
+            ..ItemMetadataFilter::default()
+        };
+        assert_eq!(filter(tech_one), [false, true]);
+        let low_meta = ItemMetadataFilter {
+            max_meta_level: Some(4),
+            ..ItemMetadataFilter::default()
+        };
+        assert_eq!(filter(low_meta), [false, true]);
+    }
+
+    #[test]
+    fn unknown_type_ids_are_an_error() {
+        let sde = fixture_sde("unknown");
+        let items =
+            get_item_data_from_db(&sde, &SdeItemSelection::TypeIds(vec![34, 902])).unwrap();
+        assert_eq!(items.len(), 2);
+
+        let error = get_item_data_from_db(&sde, &SdeItemSelection::TypeIds(vec![34, 12, 13]))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("has no type IDs 12, 13"));
+    }
+
+    #[test]
+    fn get_item_from_db_by_id() {
+        let items = get_item_data_from_db(&eve_db(), &SdeItemSelection::TypeIds(vec![22544]))
+            .unwrap();
+        assert_eq!(items[&22544].type_name, "Hulk");
+    }
+
+    #[test]
+    fn test_get_tradable_item_names_some_results() {
+        let result = get_item_data_from_db(&eve_db(), &SdeItemSelection::Tradable).unwrap();
 
         assert!(!result.is_empty());
-        for name in &result {
-            assert!(name.len() > 0);
+        for item in result.values() {
+            assert!(!item.type_name.is_empty());
         }
     }
 
     #[test]
     fn test_get_tradable_item_names_eve_db_not_found() {
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("nonexistent.db");
-        let src_path_connection = SQL_Connection::open(db_path);
+        let db_path = std::env::temp_dir()
+            .join(format!("gescheftmacher-no-sde-{}", std::process::id()))
+            .join("nonexistent.db");
 
-        match get_tradable_item_names(&src_path_connection.unwrap()) {
+        match Sde::open(&db_path) {
             Err(_) => (),
             Ok(_) => panic!("Expected an error"),
         }
@@ -425,97 +1001,30 @@ mod tests {
         let hulk_id = 22544;
         let hulk_packed_volume = 3750 as f32;
 
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        let src_path_connection = SQL_Connection::open(db_path);
-
-        let exe = std::env::current_exe().unwrap();
-        let exe_loc = exe.parent().unwrap();
-        let exe_path = Path::new(&exe_loc).join("eve.db");
-        let eve_db: SQL_Connection;
-
-        if let Err(_err) = src_path_connection {
-            eve_db = SQL_Connection::open(exe_path.clone()).unwrap()
-        } else {
-            eve_db = src_path_connection.unwrap()
-        }
-        let stored = get_stored_type_volume_packed(&eve_db, hulk_id).unwrap();
-        println!("aaaa:\n{:?}", stored);
-        assert_eq!(hulk_packed_volume, stored)
+        let items = get_item_data_from_db(&eve_db(), &SdeItemSelection::TypeIds(vec![hulk_id]))
+            .unwrap();
+        assert_eq!(hulk_packed_volume, items[&hulk_id].type_volume)
     }
 
     #[test]
     fn return_packed_volume_if_exists() {
-        let name = "Hulk";
         let hulk_packed_volume = 3750 as f32;
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        let src_path_connection = SQL_Connection::open(db_path);
-
-        let exe = std::env::current_exe().unwrap();
-        let exe_loc = exe.parent().unwrap();
-        let exe_path = Path::new(&exe_loc).join("eve.db");
-        let eve_db: SQL_Connection;
-
-        if let Err(_err) = src_path_connection {
-            eve_db = SQL_Connection::open(exe_path.clone()).unwrap()
-        } else {
-            eve_db = src_path_connection.unwrap()
-        }
-
-        let stored = get_stored_type_data(&eve_db, name).unwrap();
-        let item_id = stored.type_id;
-
-        let packed_volume = get_stored_type_volume_packed(&eve_db, item_id);
+        let items = get_item_data_from_db(&eve_db(), &SdeItemSelection::Tradable).unwrap();
 
-        let volume: f32;
-        if let Err(_err) = packed_volume {
-            volume = stored.type_volume;
-        } else {
-            volume = packed_volume.unwrap();
-        }
-
-        assert_eq!(hulk_packed_volume, volume)
+        assert_eq!(hulk_packed_volume, find_by_name(&items, "Hulk").type_volume)
     }
 
     #[test]
     fn return_regular_volume_if_packed_not_exists() {
-        let name = "Tritanium";
         let trit_volume = 0.01;
-        let curr_dir = std::env::current_dir().unwrap();
-        let db_path = Path::new(&curr_dir).join("src").join("eve.db");
-        let src_path_connection = SQL_Connection::open(db_path);
-
-        let exe = std::env::current_exe().unwrap();
-        let exe_loc = exe.parent().unwrap();
-        let exe_path = Path::new(&exe_loc).join("eve.db");
-        let eve_db: SQL_Connection;
-
-        if let Err(_err) = src_path_connection {
-            eve_db = SQL_Connection::open(exe_path.clone()).unwrap()
-        } else {
-            eve_db = src_path_connection.unwrap()
-        }
-
-        let stored = get_stored_type_data(&eve_db, name).unwrap();
-        let item_id = stored.type_id;
-
-        println!("aaaa:\n{:?}", stored);
-        let packed_volume = get_stored_type_volume_packed(&eve_db, item_id);
-
-        let volume: f32;
-        if let Err(_err) = packed_volume {
-            volume = stored.type_volume;
-        } else {
-            volume = packed_volume.unwrap();
-        }
+        let items = get_item_data_from_db(&eve_db(), &SdeItemSelection::Tradable).unwrap();
 
-        assert_eq!(trit_volume, volume)
+        assert_eq!(trit_volume, find_by_name(&items, "Tritanium").type_volume)
     }
 
     #[test]
     fn get_tradable_item_names_from_db_success_length_as_in_explorer() {
-        let result = get_tradable_item_names_from_db();
+        let result = get_item_data_from_db(&eve_db(), &SdeItemSelection::Tradable).unwrap();
 
         assert_eq!(result.len(), 12567);
     }
@@ -537,9 +1046,8 @@ mod tests {
             "Megacyte",
         ];
 
-        // replace with actual tradable item names
-        let result = get_tradable_item_names_from_db();
-        let minerals = &result[..12];
-        assert_eq!(minerals, expected_minerals);
+        let result = get_item_data_from_db(&eve_db(), &SdeItemSelection::Tradable).unwrap();
+        let names: Vec<&str> = result.values().map(|item| item.type_name.as_str()).collect();
+        assert_eq!(&names[..12], expected_minerals);
     }
 }
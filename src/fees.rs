@@ -0,0 +1,203 @@
+pub mod fees {
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    /// Broker Relations takes this much off the NPC broker fee per level.
+    pub const BROKER_RELATIONS_REDUCTION: f64 = 0.003;
+    pub const FACTION_STANDING_REDUCTION: f64 = 0.0003;
+    pub const CORP_STANDING_REDUCTION: f64 = 0.0002;
+    pub const MIN_NPC_BROKER_FEE: f64 = 0.01;
+    /// Accounting takes this share of the base sales tax off per level.
+    pub const ACCOUNTING_REDUCTION: f64 = 0.11;
+
+    /// Unmodified standings towards the owner of an NPC station.
+    #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Standings {
+        pub faction: f64,
+        pub corp: f64,
+    }
+
+    /// Order fees as the game charges them: a broker fee on every order placed, and sales
+    /// tax on top when selling. Rates are fractions, 0.03 is 3%.
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct FeesConfig {
+        pub base_sales_tax: f64,
+        pub base_broker_fee: f64,
+        pub accounting: u8,
+        pub broker_relations: u8,
+        /// Also used for `[[sources]]` that are NPC stations.
+        pub jita_standings: Standings,
+        /// Only used for destinations that are NPC stations.
+        pub abroad_standings: Standings,
+        /// Owner-set broker fee by structure ID. Skills and standings do not change these,
+        /// and any location not listed is treated as an NPC station.
+        pub structure_broker_fees: BTreeMap<String, f64>,
+    }
+
+    impl Default for FeesConfig {
+        fn default() -> Self {
+            FeesConfig {
+                base_sales_tax: 0.075,
+                base_broker_fee: 0.03,
+                accounting: 5,
+                broker_relations: 5,
+                jita_standings: Standings::default(),
+                abroad_standings: Standings::default(),
+                structure_broker_fees: BTreeMap::from([("1030049082711".to_owned(), 0.01)]),
+            }
+        }
+    }
+
+    impl FeesConfig {
+        pub fn sales_tax(&self) -> f64 {
+            self.base_sales_tax * (1.0 - ACCOUNTING_REDUCTION * self.accounting as f64)
+        }
+
+        pub fn npc_broker_fee(&self, standings: &Standings) -> f64 {
+            let fee = self.base_broker_fee
+                - BROKER_RELATIONS_REDUCTION * self.broker_relations as f64
+                - FACTION_STANDING_REDUCTION * standings.faction
+                - CORP_STANDING_REDUCTION * standings.corp;
+            fee.max(MIN_NPC_BROKER_FEE)
+        }
+
+        pub fn broker_fee_at(&self, location_id: i64, standings: &Standings) -> f64 {
+            match self.structure_broker_fees.get(&location_id.to_string()) {
+                Some(fee) => *fee,
+                None => self.npc_broker_fee(standings),
+            }
+        }
+
+        /// Placing the Jita buy order.
+        pub fn jita_buy_fee(&self, jita_station_id: i64) -> f64 {
+            self.broker_fee_at(jita_station_id, &self.jita_standings)
+        }
+
+        pub fn problems(&self) -> Vec<String> {
+            let mut problems = vec![];
+            for (key, value) in [
+                ("fees.accounting", self.accounting),
+                ("fees.broker_relations", self.broker_relations),
+            ] {
+                if value > 5 {
+                    problems.push(format!(
+                        "{} is a skill level from 0 to 5, got {}",
+                        key, value
+                    ));
+                }
+            }
+            for (key, standings) in [
+                ("fees.jita_standings", &self.jita_standings),
+                ("fees.abroad_standings", &self.abroad_standings),
+            ] {
+                for (name, value) in [("faction", standings.faction), ("corp", standings.corp)] {
+                    if !(-10.0..=10.0).contains(&value) {
+                        problems.push(format!(
+                            "{}.{} must be between -10 and 10, got {}",
+                            key, name, value
+                        ));
+                    }
+                }
+            }
+            let rates = [
+                ("fees.base_sales_tax".to_owned(), self.base_sales_tax),
+                ("fees.base_broker_fee".to_owned(), self.base_broker_fee),
+            ];
+            let structure_rates = self
+                .structure_broker_fees
+                .iter()
+                .map(|(id, fee)| (format!("fees.structure_broker_fees.{}", id), *fee));
+            for (key, value) in rates.into_iter().chain(structure_rates) {
+                if !(0.0..1.0).contains(&value) {
+                    problems.push(format!(
+                        "{} must be at least 0 and below 1, got {}",
+                        key, value
+                    ));
+                }
+            }
+            for id in self.structure_broker_fees.keys() {
+                if id.parse::<i64>().is_err() {
+                    problems.push(format!(
+                        "fees.structure_broker_fees keys are structure IDs, got {:?}",
+                        id
+                    ));
+                }
+            }
+            problems
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fees::fees::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn skills_and_standings_lower_npc_fees() {
+        let untrained = FeesConfig {
+            accounting: 0,
+            broker_relations: 0,
+            ..FeesConfig::default()
+        };
+        assert_close(untrained.sales_tax(), 0.075);
+        assert_close(untrained.npc_broker_fee(&Standings::default()), 0.03);
+
+        let trader = FeesConfig::default();
+        assert_close(trader.sales_tax(), 0.075 * 0.45);
+        let standings = Standings {
+            faction: 5.0,
+            corp: 2.5,
+        };
+        assert_close(
+            trader.npc_broker_fee(&standings),
+            0.03 - 0.015 - 0.0015 - 0.0005,
+        );
+        let perfect = Standings {
+            faction: 10.0,
+            corp: 10.0,
+        };
+        assert_close(trader.npc_broker_fee(&perfect), MIN_NPC_BROKER_FEE);
+    }
+
+    #[test]
+    fn structures_charge_their_own_broker_fee() {
+        let fees = FeesConfig::default();
+
+        assert_close(
+            fees.broker_fee_at(1030049082711, &fees.abroad_standings),
+            0.01,
+        );
+        assert_close(fees.jita_buy_fee(60003760), 0.015);
+    }
+
+    #[test]
+    fn out_of_range_fees_are_reported() {
+        let fees = FeesConfig {
+            accounting: 6,
+            jita_standings: Standings {
+                faction: 11.0,
+                corp: 0.0,
+            },
+            structure_broker_fees: [("keepstar".to_owned(), 0.5)].into(),
+            ..FeesConfig::default()
+        };
+
+        let problems = fees.problems();
+
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("fees.accounting"));
+        assert!(problems[1].starts_with("fees.jita_standings.faction"));
+        assert!(problems[2].contains("\"keepstar\""));
+    }
+}
@@ -0,0 +1,221 @@
+pub mod filters {
+    use crate::ExtendedItemData;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+    pub enum OpportunityRule {
+        SellMargin,
+        DailyProfit,
+        FreezeRate,
+        MarketRate,
+        DailyVolume,
+    }
+
+    pub const OPPORTUNITY_RULES: [OpportunityRule; 5] = [
+        OpportunityRule::SellMargin,
+        OpportunityRule::DailyProfit,
+        OpportunityRule::FreezeRate,
+        OpportunityRule::MarketRate,
+        OpportunityRule::DailyVolume,
+    ];
+
+    impl fmt::Display for OpportunityRule {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let name = match self {
+                OpportunityRule::SellMargin => "sell margin",
+                OpportunityRule::DailyProfit => "daily profit",
+                OpportunityRule::FreezeRate => "freeze rate",
+                OpportunityRule::MarketRate => "market rate",
+                OpportunityRule::DailyVolume => "daily volume",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    /// Which items are worth hauling. An item missing the number a rule looks at fails
+    /// that rule, except for items nobody sells abroad when `keep_not_seeded` is on.
+    /// Off unless the config or the side panel turns it on.
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct OpportunityFilter {
+        pub enabled: bool,
+        /// Abroad sell price after tax over the source buy order cost plus shipping,
+        /// 1.15 is a 15% margin.
+        pub min_sell_margin: f64,
+        pub min_daily_profit: f64,
+        /// Daily profit over the ISK tied up in a day of stock.
+        pub min_freeze_rate: f64,
+        /// Weeks of abroad stock on the market, more than this is saturated.
+        pub max_market_rate: f64,
+        pub min_daily_volume: f64,
+        pub keep_not_seeded: bool,
+    }
+
+    impl Default for OpportunityFilter {
+        fn default() -> Self {
+            OpportunityFilter {
+                enabled: false,
+                min_sell_margin: 1.15,
+                min_daily_profit: 30_000_000.0,
+                min_freeze_rate: 0.1,
+                max_market_rate: 1.0,
+                min_daily_volume: 10.0,
+                keep_not_seeded: true,
+            }
+        }
+    }
+
+    impl OpportunityFilter {
+        pub fn passes(&self, rule: OpportunityRule, item: &ExtendedItemData) -> bool {
+            let at_least = |value: Option<f64>, min: f64| value.is_some_and(|v| v >= min);
+            match rule {
+                OpportunityRule::SellMargin => {
+                    at_least(item.margin_buy_order.map(|m| 1.0 + m), self.min_sell_margin)
+                }
+                OpportunityRule::DailyProfit => {
+                    at_least(item.profit_buy_order_daily, self.min_daily_profit)
+                }
+                OpportunityRule::FreezeRate => at_least(item.freeze_rate, self.min_freeze_rate),
+                OpportunityRule::MarketRate => item
+                    .abroad_stocked_ratio
+                    .is_some_and(|ratio| ratio <= self.max_market_rate),
+                OpportunityRule::DailyVolume => {
+                    at_least(item.abroad_avg_daily, self.min_daily_volume)
+                }
+            }
+        }
+
+        /// The rules `item` breaks, empty when it is kept.
+        pub fn failed_rules(&self, item: &ExtendedItemData) -> Vec<OpportunityRule> {
+            if !self.enabled || (self.keep_not_seeded && item.not_seeded_abroad) {
+                return vec![];
+            }
+            OPPORTUNITY_RULES
+                .into_iter()
+                .filter(|rule| !self.passes(*rule, item))
+                .collect()
+        }
+
+        pub fn apply(&self, items: &[ExtendedItemData]) -> (Vec<bool>, FilterReport) {
+            let mut report = FilterReport {
+                total: items.len(),
+                ..FilterReport::default()
+            };
+            let kept = items
+                .iter()
+                .map(|item| {
+                    let failed = self.failed_rules(item);
+                    for rule in &failed {
+                        *report.removed_by_rule.entry(*rule).or_default() += 1;
+                    }
+                    if failed.is_empty() {
+                        report.kept += 1;
+                    }
+                    failed.is_empty()
+                })
+                .collect();
+            (kept, report)
+        }
+    }
+
+    /// An item breaking several rules is counted under each of them.
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct FilterReport {
+        pub total: usize,
+        pub kept: usize,
+        pub removed_by_rule: BTreeMap<OpportunityRule, usize>,
+    }
+
+    impl FilterReport {
+        pub fn removed_by(&self, rule: OpportunityRule) -> usize {
+            self.removed_by_rule.get(&rule).copied().unwrap_or(0)
+        }
+
+        /// Shown above the table, so rows the thresholds hide never go unnoticed.
+        pub fn hidden_note(&self) -> Option<String> {
+            let hidden = self.total - self.kept;
+            (hidden > 0).then(|| {
+                format!(
+                    "{} of {} items hidden by the opportunity thresholds, \
+                    untick \"Only show opportunities\" to see them",
+                    hidden, self.total
+                )
+            })
+        }
+
+        pub fn lines(&self) -> Vec<String> {
+            let mut lines = vec![format!("{} of {} items kept", self.kept, self.total)];
+            for rule in OPPORTUNITY_RULES {
+                lines.push(format!("{}: {} removed", rule, self.removed_by(rule)));
+            }
+            lines
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filters::filters::*;
+    use crate::ExtendedItemData;
+
+    fn enabled() -> OpportunityFilter {
+        OpportunityFilter {
+            enabled: true,
+            ..OpportunityFilter::default()
+        }
+    }
+
+    fn opportunity(margin: f64, daily_profit: f64) -> ExtendedItemData {
+        ExtendedItemData {
+            margin_buy_order: Some(margin),
+            profit_buy_order_daily: Some(daily_profit),
+            freeze_rate: Some(0.2),
+            abroad_stocked_ratio: Some(0.5),
+            abroad_avg_daily: Some(50.0),
+            ..ExtendedItemData::default()
+        }
+    }
+
+    #[test]
+    fn default_thresholds_keep_a_good_opportunity() {
+        let filter = enabled();
+
+        assert!(filter
+            .failed_rules(&opportunity(0.5, 40_000_000.0))
+            .is_empty());
+        assert_eq!(
+            filter.failed_rules(&opportunity(0.1, 40_000_000.0)),
+            vec![OpportunityRule::SellMargin]
+        );
+    }
+
+    #[test]
+    fn report_counts_every_rule_an_item_breaks() {
+        let items = vec![
+            opportunity(0.5, 40_000_000.0),
+            opportunity(0.1, 40_000_000.0),
+            opportunity(0.1, 1_000.0),
+            ExtendedItemData::default(),
+            ExtendedItemData {
+                not_seeded_abroad: true,
+                ..ExtendedItemData::default()
+            },
+        ];
+
+        let (kept, report) = enabled().apply(&items);
+
+        assert_eq!(kept, vec![true, false, false, false, true]);
+        assert_eq!(report.kept, 2);
+        assert_eq!(report.removed_by(OpportunityRule::SellMargin), 3);
+        assert_eq!(report.removed_by(OpportunityRule::DailyProfit), 2);
+        assert_eq!(report.removed_by(OpportunityRule::DailyVolume), 1);
+
+        assert!(report.hidden_note().unwrap().starts_with("3 of 5 items hidden"));
+
+        let disabled = OpportunityFilter::default();
+        assert_eq!(disabled.apply(&items).1.kept, 5);
+        assert_eq!(disabled.apply(&items).1.hidden_note(), None);
+    }
+}
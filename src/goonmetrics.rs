@@ -1,4 +1,5 @@
 pub mod goonmetrics {
+    use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -18,10 +19,13 @@ pub mod goonmetrics {
         Type(ItemType),
     }
 
+    /// Goonmetrics sends every value as element text and leaves elements empty when
+    /// there is nothing to report, so an empty value is `None` rather than an error.
     #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[serde(try_from = "RawItemType")]
     pub struct ItemType {
         pub id: i32,
-        pub updated: String,
+        pub updated: Option<DateTime<Utc>>,
         pub all: All,
         pub buy: Buy,
         pub sell: Sell,
@@ -29,16 +33,177 @@ pub mod goonmetrics {
 
     #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
     pub struct All {
-        pub weekly_movement: String,
+        pub weekly_movement: Option<f64>,
     }
     #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
     pub struct Sell {
-        pub listed: String,
-        pub min: String,
+        pub listed: Option<i64>,
+        pub min: Option<f64>,
     }
     #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
     pub struct Buy {
-        pub listed: String,
-        pub max: String,
+        pub listed: Option<i64>,
+        pub max: Option<f64>,
+    }
+
+    /// The element text as sent, parsed into `ItemType` once the type ID is known
+    /// so a bad value can name the type it belongs to.
+    #[derive(Deserialize)]
+    struct RawItemType {
+        id: i32,
+        #[serde(default)]
+        updated: String,
+        all: RawAll,
+        buy: RawBuy,
+        sell: RawSell,
+    }
+
+    #[derive(Deserialize)]
+    struct RawAll {
+        #[serde(default)]
+        weekly_movement: String,
+    }
+    #[derive(Deserialize)]
+    struct RawSell {
+        #[serde(default)]
+        listed: String,
+        #[serde(default)]
+        min: String,
+    }
+    #[derive(Deserialize)]
+    struct RawBuy {
+        #[serde(default)]
+        listed: String,
+        #[serde(default)]
+        max: String,
+    }
+
+    impl TryFrom<RawItemType> for ItemType {
+        type Error = String;
+
+        fn try_from(raw: RawItemType) -> Result<Self, Self::Error> {
+            let id = raw.id;
+            Ok(ItemType {
+                id,
+                updated: parse_timestamp(id, "updated", &raw.updated)?,
+                all: All {
+                    weekly_movement: parse_decimal(
+                        id,
+                        "all.weekly_movement",
+                        &raw.all.weekly_movement,
+                    )?,
+                },
+                buy: Buy {
+                    listed: parse_quantity(id, "buy.listed", &raw.buy.listed)?,
+                    max: parse_decimal(id, "buy.max", &raw.buy.max)?,
+                },
+                sell: Sell {
+                    listed: parse_quantity(id, "sell.listed", &raw.sell.listed)?,
+                    min: parse_decimal(id, "sell.min", &raw.sell.min)?,
+                },
+            })
+        }
+    }
+
+    fn parse_error(type_id: i32, field: &str, value: &str) -> String {
+        format!("type {}: cannot parse {} from {:?}", type_id, field, value)
+    }
+
+    /// Accepts plain and scientific notation, `f64` parsing handles both.
+    pub fn parse_decimal(type_id: i32, field: &str, value: &str) -> Result<Option<f64>, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(Some(number)),
+            _ => Err(parse_error(type_id, field, value)),
+        }
+    }
+
+    /// Listed volumes are whole units, but goonmetrics sometimes sends them as
+    /// decimals ("12.0", "1e3"); those are rounded instead of rejected.
+    pub fn parse_quantity(type_id: i32, field: &str, value: &str) -> Result<Option<i64>, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(quantity) = value.parse::<i64>() {
+            return Ok(Some(quantity));
+        }
+        match parse_decimal(type_id, field, value)? {
+            Some(quantity) if quantity.abs() < i64::MAX as f64 => Ok(Some(quantity.round() as i64)),
+            _ => Err(parse_error(type_id, field, value)),
+        }
+    }
+
+    pub fn parse_timestamp(
+        type_id: i32,
+        field: &str,
+        value: &str,
+    ) -> Result<Option<DateTime<Utc>>, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        DateTime::parse_from_rfc3339(value)
+            .map(|date| Some(date.with_timezone(&Utc)))
+            .map_err(|_| parse_error(type_id, field, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::goonmetrics::goonmetrics::*;
+    use serde_xml_rs::from_str;
+
+    fn parse(type_xml: &str) -> Result<Goonmetrics, serde_xml_rs::Error> {
+        from_str(&format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><goonmetrics><price_data version="2">{}</price_data></goonmetrics>"#,
+            type_xml
+        ))
+    }
+
+    fn first_type(data: &Goonmetrics) -> &ItemType {
+        let Types::Type(item_type) = &data.price_data.types[0];
+        item_type
+    }
+
+    #[test]
+    fn values_are_typed() {
+        let data = parse(r#"<type id="34"><updated>2024-05-03T13:36:22Z</updated><all><weekly_movement>1.0005e3</weekly_movement></all><buy><listed>300</listed><max>4.5</max></buy><sell><listed>200.0</listed><min>5.1</min></sell></type>"#).unwrap();
+        let item_type = first_type(&data);
+
+        assert_eq!(
+            item_type.updated.unwrap().to_rfc3339(),
+            "2024-05-03T13:36:22+00:00"
+        );
+        assert_eq!(item_type.all.weekly_movement, Some(1000.5));
+        assert_eq!(item_type.buy.listed, Some(300));
+        assert_eq!(item_type.buy.max, Some(4.5));
+        assert_eq!(item_type.sell.listed, Some(200));
+        assert_eq!(item_type.sell.min, Some(5.1));
+    }
+
+    #[test]
+    fn empty_values_are_none() {
+        let data = parse(r#"<type id="34"><updated></updated><all><weekly_movement/></all><buy><listed></listed><max/></buy><sell><listed>0</listed><min></min></sell></type>"#).unwrap();
+        let item_type = first_type(&data);
+
+        assert_eq!(item_type.updated, None);
+        assert_eq!(item_type.all.weekly_movement, None);
+        assert_eq!(item_type.buy.listed, None);
+        assert_eq!(item_type.buy.max, None);
+        assert_eq!(item_type.sell.listed, Some(0));
+        assert_eq!(item_type.sell.min, None);
+    }
+
+    #[test]
+    fn parse_errors_name_the_type() {
+        let error = parse(r#"<type id="11192"><updated>2024-05-03T13:36:22Z</updated><all><weekly_movement>lots</weekly_movement></all><buy><listed>1</listed><max>1</max></buy><sell><listed>1</listed><min>1</min></sell></type>"#).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("type 11192"), "{}", message);
+        assert!(message.contains("all.weekly_movement"), "{}", message);
     }
 }
@@ -0,0 +1,431 @@
+pub mod history {
+    use crate::datagetter::datagetter::{
+        get_app_data_dir, FailedBatch, FetchReport, ItemData, Result,
+    };
+    use crate::esi::esi::ESI_BASE_URL;
+    use crate::http::http::HttpClient;
+    use chrono::{Duration, NaiveDate, Utc};
+    use futures::stream::{self, StreamExt};
+    use rusqlite::{params, Connection as SQL_Connection};
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Instant;
+
+    /// One row of ESI `/markets/{region_id}/history/`. Days without trades are omitted by ESI.
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    pub struct MarketHistoryDay {
+        pub date: String,
+        pub average: f64,
+        pub highest: f64,
+        pub lowest: f64,
+        pub order_count: i64,
+        pub volume: i64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    pub struct HistoryStats {
+        pub days: i64,
+        pub avg_daily_volume: f64,
+        pub median_price: f64,
+        /// Standard deviation of day-to-day relative changes of the average price.
+        pub volatility: f64,
+    }
+
+    impl HistoryStats {
+        /// `window_days` is the length of the window `days` was taken from, so
+        /// days ESI left out because nothing traded count as zero volume.
+        pub fn from_days(days: &[MarketHistoryDay], window_days: i64) -> Option<HistoryStats> {
+            if days.is_empty() || window_days <= 0 {
+                return None;
+            }
+            let total_volume: i64 = days.iter().map(|day| day.volume).sum();
+
+            let mut prices: Vec<f64> = days.iter().map(|day| day.average).collect();
+            prices.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let middle = prices.len() / 2;
+            let median_price = if prices.len().is_multiple_of(2) {
+                (prices[middle - 1] + prices[middle]) / 2.0
+            } else {
+                prices[middle]
+            };
+
+            let returns: Vec<f64> = days
+                .windows(2)
+                .filter(|pair| pair[0].average > 0.0)
+                .map(|pair| pair[1].average / pair[0].average - 1.0)
+                .collect();
+            let volatility = if returns.len() < 2 {
+                0.0
+            } else {
+                let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+                let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+                    / (returns.len() - 1) as f64;
+                variance.sqrt()
+            };
+
+            Some(HistoryStats {
+                days: days.len() as i64,
+                avg_daily_volume: total_volume as f64 / window_days as f64,
+                median_price,
+                volatility,
+            })
+        }
+    }
+
+    pub struct HistoryStore {
+        conn: SQL_Connection,
+    }
+
+    impl HistoryStore {
+        pub fn open(path: &Path) -> Result<Self> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let conn = SQL_Connection::open(path)
+                .map_err(|e| format!("cannot open history db {:?}: {}", path, e))?;
+            HistoryStore::init(conn)
+        }
+
+        pub fn open_default() -> Result<Self> {
+            HistoryStore::open(&get_app_data_dir().join("market_history.db"))
+        }
+
+        #[cfg(test)]
+        pub fn open_in_memory() -> Result<Self> {
+            HistoryStore::init(SQL_Connection::open_in_memory()?)
+        }
+
+        fn init(conn: SQL_Connection) -> Result<Self> {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS market_history (
+                    region_id INTEGER NOT NULL,
+                    type_id INTEGER NOT NULL,
+                    date TEXT NOT NULL,
+                    average REAL NOT NULL,
+                    highest REAL NOT NULL,
+                    lowest REAL NOT NULL,
+                    order_count INTEGER NOT NULL,
+                    volume INTEGER NOT NULL,
+                    PRIMARY KEY (region_id, type_id, date)
+                )",
+            )?;
+            Ok(HistoryStore { conn })
+        }
+
+        pub fn store_history(
+            &mut self,
+            region_id: i32,
+            type_id: i32,
+            days: &[MarketHistoryDay],
+        ) -> Result<()> {
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO market_history
+                    (region_id, type_id, date, average, highest, lowest, order_count, volume)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )?;
+                for day in days {
+                    stmt.execute(params![
+                        region_id,
+                        type_id,
+                        day.date,
+                        day.average,
+                        day.highest,
+                        day.lowest,
+                        day.order_count,
+                        day.volume
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        }
+
+        pub fn get_history(
+            &self,
+            region_id: i32,
+            type_id: i32,
+            since: NaiveDate,
+        ) -> Result<Vec<MarketHistoryDay>> {
+            let mut stmt = self.conn.prepare(
+                "SELECT date, average, highest, lowest, order_count, volume
+                FROM market_history
+                WHERE region_id = ?1 AND type_id = ?2 AND date >= ?3
+                ORDER BY date",
+            )?;
+            let rows = stmt.query_map(
+                params![region_id, type_id, since.format("%Y-%m-%d").to_string()],
+                |row| {
+                    Ok(MarketHistoryDay {
+                        date: row.get(0)?,
+                        average: row.get(1)?,
+                        highest: row.get(2)?,
+                        lowest: row.get(3)?,
+                        order_count: row.get(4)?,
+                        volume: row.get(5)?,
+                    })
+                },
+            )?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+
+        pub fn get_latest_date(&self, region_id: i32, type_id: i32) -> Result<Option<String>> {
+            Ok(self.conn.query_row(
+                "SELECT MAX(date) FROM market_history WHERE region_id = ?1 AND type_id = ?2",
+                params![region_id, type_id],
+                |row| row.get(0),
+            )?)
+        }
+
+        /// Stats over the last `window_days` days for every type that has history.
+        pub fn get_stats_by_type(
+            &self,
+            region_id: i32,
+            type_ids: &[i32],
+            window_days: i64,
+        ) -> Result<HashMap<i32, HistoryStats>> {
+            let since = (Utc::now() - Duration::days(window_days)).date_naive();
+            let mut result = HashMap::new();
+            for type_id in type_ids {
+                let days = self.get_history(region_id, *type_id, since)?;
+                if let Some(stats) = HistoryStats::from_days(&days, window_days) {
+                    result.insert(*type_id, stats);
+                }
+            }
+            Ok(result)
+        }
+    }
+
+    pub async fn get_market_history(
+        http: &HttpClient,
+        base_url: &str,
+        region_id: i32,
+        type_id: i32,
+    ) -> Result<Vec<MarketHistoryDay>> {
+        let url = format!(
+            "{}/markets/{}/history/?datasource=tranquility&type_id={}",
+            base_url.trim_end_matches('/'),
+            region_id,
+            type_id
+        );
+        http.fetch(
+            |client| client.get(&url),
+            |_, body| Ok(serde_json::from_str(body)?),
+        )
+        .await
+    }
+
+    /// Pulls history for every type whose stored series does not reach yesterday yet,
+    /// at most `max_concurrent_requests` types at a time. ESI recomputes history once a day,
+    /// so re-running the same day is cheap. The report counts the types stored; types ESI
+    /// fails on, often ones that never traded, are reported instead of aborting.
+    pub async fn ingest_market_history(
+        http: &HttpClient,
+        store: &mut HistoryStore,
+        base_url: &str,
+        region_id: i32,
+        type_ids: &[i32],
+        max_concurrent_requests: usize,
+    ) -> Result<FetchReport<usize>> {
+        let started = Instant::now();
+        let yesterday = (Utc::now() - Duration::days(1))
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string();
+        let mut outdated = vec![];
+        for type_id in type_ids {
+            let latest = store.get_latest_date(region_id, *type_id)?;
+            if latest.is_none_or(|latest| latest < yesterday) {
+                outdated.push(*type_id);
+            }
+        }
+
+        let mut fetched = stream::iter(outdated)
+            .map(|type_id| async move {
+                let result = get_market_history(http, base_url, region_id, type_id).await;
+                (type_id, result)
+            })
+            .buffered(max_concurrent_requests.max(1));
+        let mut report = FetchReport {
+            data: 0,
+            failed_batches: vec![],
+            elapsed: std::time::Duration::ZERO,
+        };
+        while let Some((type_id, result)) = fetched.next().await {
+            match result {
+                Ok(days) => {
+                    store.store_history(region_id, type_id, &days)?;
+                    report.data += 1;
+                }
+                Err(e) => report.failed_batches.push(FailedBatch {
+                    type_ids: vec![type_id],
+                    cause: e.to_string(),
+                }),
+            }
+        }
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    pub async fn ingest_market_history_from_esi(
+        http: &HttpClient,
+        store: &mut HistoryStore,
+        region_id: i32,
+        type_ids: &[i32],
+        max_concurrent_requests: usize,
+    ) -> Result<FetchReport<usize>> {
+        ingest_market_history(
+            http,
+            store,
+            ESI_BASE_URL,
+            region_id,
+            type_ids,
+            max_concurrent_requests,
+        )
+        .await
+    }
+
+    /// Sets the history of the destination at `location_id` on every item.
+    pub fn attach_history_stats(
+        items: &mut [ItemData],
+        location_id: i64,
+        stats: &HashMap<i32, HistoryStats>,
+    ) {
+        for item in items.iter_mut() {
+            item.markets.entry(location_id).or_default().history =
+                stats.get(&item.type_id).cloned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::history::*;
+    use crate::http::http::HttpClient;
+    use chrono::{Duration, Utc};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn day(date: &str, average: f64, volume: i64) -> MarketHistoryDay {
+        MarketHistoryDay {
+            date: date.to_owned(),
+            average,
+            highest: average,
+            lowest: average,
+            order_count: 1,
+            volume,
+        }
+    }
+
+    #[test]
+    fn stats_count_missing_days_as_zero_volume() {
+        let days = vec![
+            day("2024-05-01", 100.0, 10),
+            day("2024-05-02", 110.0, 20),
+            day("2024-05-03", 99.0, 30),
+        ];
+        let stats = HistoryStats::from_days(&days, 6).unwrap();
+
+        assert_eq!(stats.days, 3);
+        assert_eq!(stats.avg_daily_volume, 10.0);
+        assert_eq!(stats.median_price, 100.0);
+        // returns: +10%, -10%
+        assert!((stats.volatility - 0.1414).abs() < 0.001);
+    }
+
+    #[test]
+    fn stats_need_history() {
+        assert_eq!(HistoryStats::from_days(&[], 30), None);
+    }
+
+    #[test]
+    fn store_roundtrip_and_stats_window() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let today = Utc::now().date_naive();
+        let recent = (today - Duration::days(2)).format("%Y-%m-%d").to_string();
+        let old = (today - Duration::days(60)).format("%Y-%m-%d").to_string();
+        store
+            .store_history(10000002, 34, &[day(&old, 3.0, 1000), day(&recent, 5.0, 70)])
+            .unwrap();
+        // Re-ingesting the same day replaces it instead of duplicating.
+        store
+            .store_history(10000002, 34, &[day(&recent, 5.0, 70)])
+            .unwrap();
+
+        assert_eq!(store.get_latest_date(10000002, 34).unwrap(), Some(recent));
+        let stats = store.get_stats_by_type(10000002, &[34, 35], 7).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[&34].avg_daily_volume, 10.0);
+        assert_eq!(stats[&34].median_price, 5.0);
+    }
+
+    #[tokio::test]
+    async fn ingest_from_mock_esi() {
+        let server = MockServer::start().await;
+        let yesterday = (Utc::now() - Duration::days(1))
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string();
+        Mock::given(method("GET"))
+            .and(path("/markets/10000002/history/"))
+            .and(query_param("type_id", "34"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![day(&yesterday, 4.2, 1_000_000)]),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let http = HttpClient::default();
+        let report = ingest_market_history(&http, &mut store, &server.uri(), 10000002, &[34], 4)
+            .await
+            .unwrap();
+        assert_eq!(report.data, 1);
+
+        // Already up to date, mock expects exactly one request.
+        let report = ingest_market_history(&http, &mut store, &server.uri(), 10000002, &[34], 4)
+            .await
+            .unwrap();
+        assert_eq!(report.data, 0);
+    }
+
+    #[tokio::test]
+    async fn ingest_reports_failed_types_and_stores_the_rest() {
+        let server = MockServer::start().await;
+        let yesterday = (Utc::now() - Duration::days(1))
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string();
+        Mock::given(method("GET"))
+            .and(path("/markets/10000002/history/"))
+            .and(query_param("type_id", "35"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/markets/10000002/history/"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![day(&yesterday, 4.2, 1_000)]),
+            )
+            .mount(&server)
+            .await;
+
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let http = crate::http::tests::fast_retry_client(0);
+        let report =
+            ingest_market_history(&http, &mut store, &server.uri(), 10000002, &[34, 35, 36], 2)
+                .await
+                .unwrap();
+
+        assert_eq!(report.data, 2);
+        assert_eq!(report.failed_type_ids(), vec![35]);
+        assert!(report.failed_batches[0].cause.contains("500"));
+        assert_eq!(store.get_latest_date(10000002, 34).unwrap(), Some(yesterday.clone()));
+        assert_eq!(store.get_latest_date(10000002, 36).unwrap(), Some(yesterday));
+        assert_eq!(store.get_latest_date(10000002, 35).unwrap(), None);
+    }
+}
@@ -0,0 +1,402 @@
+pub mod http {
+    use crate::datagetter::datagetter::{FetchOptions, Result};
+    use rand::Rng;
+    use reqwest::header::{HeaderMap, RETRY_AFTER};
+    use reqwest::{RequestBuilder, StatusCode};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+    use tokio::time::{sleep, Instant};
+
+    /// Below this many remaining errors in the ESI error window every request waits for the reset.
+    const ESI_ERROR_LIMIT_LOW_WATER: i64 = 10;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RetryPolicy {
+        pub max_retries: u32,
+        pub base_delay: Duration,
+        pub max_delay: Duration,
+    }
+
+    impl Default for RetryPolicy {
+        fn default() -> Self {
+            RetryPolicy {
+                max_retries: 4,
+                base_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(30),
+            }
+        }
+    }
+
+    impl RetryPolicy {
+        /// Exponential backoff with "equal jitter": half of the delay is fixed, half random.
+        pub fn backoff(&self, attempt: u32) -> Duration {
+            let exponential = self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(self.max_delay);
+            exponential.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        }
+    }
+
+    struct Bucket {
+        tokens: f64,
+        last_refill: Instant,
+        paused_until: Option<Instant>,
+    }
+
+    /// Token bucket shared by every provider, so goonmetrics and ESI batches running
+    /// side by side still stay under one request budget.
+    pub struct RateLimiter {
+        /// `None` means no limit, only ESI error-limit pauses apply.
+        requests_per_second: Option<f64>,
+        burst: f64,
+        bucket: Mutex<Bucket>,
+    }
+
+    impl RateLimiter {
+        pub fn new(requests_per_second: f64, burst: u32) -> Self {
+            RateLimiter::build(Some(requests_per_second), burst as f64)
+        }
+
+        pub fn unlimited() -> Self {
+            RateLimiter::build(None, 1.0)
+        }
+
+        fn build(requests_per_second: Option<f64>, burst: f64) -> Self {
+            RateLimiter {
+                requests_per_second,
+                burst,
+                bucket: Mutex::new(Bucket {
+                    tokens: burst,
+                    last_refill: Instant::now(),
+                    paused_until: None,
+                }),
+            }
+        }
+
+        pub async fn acquire(&self) {
+            loop {
+                let wait = {
+                    let mut bucket = self.bucket.lock().await;
+                    let now = Instant::now();
+                    match bucket.paused_until {
+                        Some(until) if until > now => until - now,
+                        _ => match self.requests_per_second {
+                            None => return,
+                            Some(rate) => {
+                                let elapsed = (now - bucket.last_refill).as_secs_f64();
+                                bucket.tokens = (bucket.tokens + elapsed * rate).min(self.burst);
+                                bucket.last_refill = now;
+                                if bucket.tokens >= 1.0 {
+                                    bucket.tokens -= 1.0;
+                                    return;
+                                }
+                                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+                            }
+                        },
+                    }
+                };
+                sleep(wait).await;
+            }
+        }
+
+        /// Holds back every request through this limiter for `duration`.
+        pub async fn pause_for(&self, duration: Duration) {
+            let until = Instant::now() + duration;
+            let mut bucket = self.bucket.lock().await;
+            if bucket.paused_until.is_none_or(|current| current < until) {
+                bucket.paused_until = Some(until);
+            }
+        }
+    }
+
+    /// reqwest client with a per-request timeout, the shared rate limiter and retries.
+    #[derive(Clone)]
+    pub struct HttpClient {
+        client: reqwest::Client,
+        limiter: Arc<RateLimiter>,
+        retry: RetryPolicy,
+    }
+
+    impl Default for HttpClient {
+        fn default() -> Self {
+            HttpClient::new(&FetchOptions::default(), Arc::new(RateLimiter::unlimited()))
+                .expect("default http client")
+        }
+    }
+
+    impl HttpClient {
+        pub fn new(options: &FetchOptions, limiter: Arc<RateLimiter>) -> Result<Self> {
+            Ok(HttpClient {
+                client: reqwest::Client::builder()
+                    .timeout(options.request_timeout)
+                    .build()?,
+                limiter,
+                retry: options.retry.clone(),
+            })
+        }
+
+        /// Sends the request built by `build` until `parse` accepts a successful response.
+        /// Network errors, 408/420/429/5xx and unparseable bodies (HTML error pages)
+        /// are retried with backoff; other 4xx fail right away.
+        pub async fn fetch<T, B, P>(&self, build: B, parse: P) -> Result<T>
+        where
+            B: Fn(&reqwest::Client) -> RequestBuilder,
+            P: Fn(&HeaderMap, &str) -> Result<T>,
+        {
+            let mut attempt = 0;
+            loop {
+                self.limiter.acquire().await;
+                let (error, retry_after) = match build(&self.client).send().await {
+                    Err(e) => (format!("{}", e), None),
+                    Ok(res) => {
+                        let status = res.status();
+                        let headers = res.headers().clone();
+                        self.respect_esi_error_limit(&headers).await;
+                        let retry_after = get_retry_after(&headers);
+                        match res.text().await {
+                            Err(e) => (format!("{}", e), retry_after),
+                            Ok(body) if status.is_success() => match parse(&headers, &body) {
+                                Ok(parsed) => return Ok(parsed),
+                                Err(e) => (format!("unexpected response body: {}", e), None),
+                            },
+                            Ok(_) if is_retryable(status) => {
+                                (format!("HTTP {}", status), retry_after)
+                            }
+                            Ok(_) => return Err(format!("HTTP {}", status).into()),
+                        }
+                    }
+                };
+
+                if attempt >= self.retry.max_retries {
+                    return Err(
+                        format!("giving up after {} attempts: {}", attempt + 1, error).into(),
+                    );
+                }
+                let backoff = self.retry.backoff(attempt);
+                let delay = retry_after.map_or(backoff, |retry_after| retry_after.max(backoff));
+                log::warn!("request failed ({}), retrying in {:?}", error, delay);
+                sleep(delay).await;
+                attempt += 1;
+            }
+        }
+
+        async fn respect_esi_error_limit(&self, headers: &HeaderMap) {
+            let remain = get_header_number(headers, "x-esi-error-limit-remain");
+            let reset = get_header_number(headers, "x-esi-error-limit-reset");
+            if let (Some(remain), Some(reset)) = (remain, reset) {
+                if remain < ESI_ERROR_LIMIT_LOW_WATER {
+                    log::warn!("ESI error limit low ({} left), pausing {}s", remain, reset);
+                    self.limiter
+                        .pause_for(Duration::from_secs(reset.max(0) as u64))
+                        .await;
+                }
+            }
+        }
+    }
+
+    fn is_retryable(status: StatusCode) -> bool {
+        status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+            // ESI answers 420 once the error limit is exhausted
+            || status.as_u16() == 420
+    }
+
+    fn get_header_number(headers: &HeaderMap, name: &str) -> Option<i64> {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<i64>().ok())
+    }
+
+    /// Only the delay-seconds form of `Retry-After`, which is what ESI and goonmetrics send.
+    fn get_retry_after(headers: &HeaderMap) -> Option<Duration> {
+        get_header_number(headers, RETRY_AFTER.as_str())
+            .map(|seconds| Duration::from_secs(seconds.max(0) as u64))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::datagetter::datagetter::{FetchOptions, Result};
+    use crate::http::http::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    pub fn fast_retry_client(max_retries: u32) -> HttpClient {
+        let options = FetchOptions {
+            max_concurrent_requests: 4,
+            request_timeout: Duration::from_secs(5),
+            retry: RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(50),
+            },
+        };
+        HttpClient::new(&options, Arc::new(RateLimiter::unlimited())).unwrap()
+    }
+
+    async fn get_body(http: &HttpClient, url: &str) -> Result<String> {
+        http.fetch(
+            |client| client.get(url),
+            |_, body| {
+                if body.starts_with("<html>") {
+                    return Err("html error page".into());
+                }
+                Ok(body.to_owned())
+            },
+        )
+        .await
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for _ in 0..20 {
+            let first = policy.backoff(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let capped = policy.backoff(8);
+            assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_bad_gateway_until_success() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let body = get_body(&fast_retry_client(3), &server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(body, "ok");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_html_error_pages() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>rate limited</html>"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let body = get_body(&fast_retry_client(3), &server.uri())
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let result = get_body(&fast_retry_client(2), &server.uri()).await;
+
+        assert!(result.is_err());
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let result = get_body(&fast_retry_client(3), &server.uri()).await;
+
+        assert!(result.is_err());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_header_is_respected() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let started = Instant::now();
+        get_body(&fast_retry_client(3), &server.uri())
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn low_esi_error_limit_pauses_next_request() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/first"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("X-ESI-Error-Limit-Remain", "3")
+                    .insert_header("X-ESI-Error-Limit-Reset", "1")
+                    .set_body_string("ok"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/second"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let http = fast_retry_client(0);
+        get_body(&http, &format!("{}/first", server.uri()))
+            .await
+            .unwrap();
+        let started = Instant::now();
+        get_body(&http, &format!("{}/second", server.uri()))
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn token_bucket_spreads_requests() {
+        let limiter = RateLimiter::new(20.0, 1);
+        let started = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        // first token is there right away, four more at 50ms each
+        assert!(started.elapsed() >= Duration::from_millis(190));
+    }
+}
@@ -1,175 +1,762 @@
 use serde::{Deserialize, Serialize};
 use serde_xml_rs::from_str;
 
+use clap::Parser;
 use error_chain::error_chain;
 use struct_field_names_as_array::FieldNamesAsSlice;
 use tokio;
 
+mod cache;
+mod cli;
+mod config;
 mod ui;
-use ui::ui::{render_ui, TradeItemViewManager, TradeItemViewManagerInitData};
+use cache::cache::{CachedProvider, PriceCache};
+use cli::cli::{AbroadPriceSource, Cli, Command, DailyVolumeEstimate, PriceSource};
+use config::config::{Config, Destination, Source};
+use ui::ui::{render_ui, ProfileSwitch, TradeItemViewManager, TradeItemViewManagerInitData};
 mod datagetter;
 mod goonmetrics;
+mod history;
+mod market_groups;
+mod http;
+mod esi;
+mod fees;
+mod filters;
+mod provider;
+mod sde;
+mod sso;
+use esi::esi::{EsiMarketProvider, EsiStructureMarketProvider};
+use http::http::{HttpClient, RateLimiter, RetryPolicy};
+use history::history::{attach_history_stats, ingest_market_history_from_esi, HistoryStore};
+use market_groups::market_groups::{get_type_ids_in_market_groups, MarketGroupTree};
+use sde::sde::{import_sde, resolve_sde_path, rollback_sde, Sde};
+use sso::sso::{SsoClient, SsoConfig, TokenStore};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
 use datagetter::datagetter::{
-    get_item_data_from_api, get_item_data_from_db, get_tradable_item_names_from_db, merge_trade_data, ItemData, TradeData
+    get_item_data_from_db, merge_trade_data, SdeItemSelection, FetchOptions, ItemData,
+    ItemMetadata, TradeData, TradeDataByType
 };
-
-const DELIVERY_PRICE_PER_CUBOMETR: f32 = 850.0;
-const MIN_SELL_MARGIN_THRESHOLD: f32 = 1.15;
-const JITA_TAXRATE: f64 = 0.0108;
-const PROFIT_THRESHOLD: i64 = 30000000;
-const FREEZE_RATE_THRESHOLD: f32 = 0.1;
-const MARKET_RATE_THRESHOLD: i32 = 1;
-const DAILY_VOL_THRESHOLD: i64 = 10;
-const ABROAD_TAX_VALUE: f64 = 0.056;
+use provider::provider::{GoonmetricsProvider, MarketDataProvider};
 
 error_chain! {
+    links {
+        Datagetter(datagetter::datagetter::Error, datagetter::datagetter::ErrorKind);
+    }
     foreign_links {
         Io(std::io::Error);
         HttpRequest(reqwest::Error);
     }
 }
 
-#[derive(Debug, PartialEq, Clone, FieldNamesAsSlice, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Clone, Default, FieldNamesAsSlice, Deserialize, Serialize)]
 pub struct ExtendedItemData {
     type_id: i32,
     type_volume: f32,
     type_name: String,
-    jita_trade_data: TradeData,
-    jita_buy_with_tax: f64,
-    abroad_trade_data: TradeData,
-    abroad_stocked_ratio: f64,
+    market_group_id: Option<i32>,
+    metadata: ItemMetadata,
+    /// Name of the hub the source columns below are for.
+    source: String,
+    /// Name of the destination the abroad columns below are for.
+    destination: String,
+    not_seeded_abroad: bool,
+    stale_quote: bool,
+    source_trade_data: Option<TradeData>,
+    source_buy_with_tax: Option<f64>,
+    abroad_trade_data: Option<TradeData>,
+    abroad_stocked_ratio: Option<f64>,
     shipping_price: f64,
-    abroad_sell_taxed: f64,
-    abroad_avg_daily: f64,
-    profit_jita_buy_per_unit: f64,
-    profit_jita_buy_daily: f64,
-    margin_jita_buy: f64,
-    money_freeze_buy: f64,
-    freeze_rate: f64,
+    /// Buy order price at the source with broker fee and shipping.
+    landed_cost: Option<f64>,
+    abroad_sell_taxed: Option<f64>,
+    abroad_avg_daily: Option<f64>,
+    abroad_median_price: Option<f64>,
+    abroad_volatility: Option<f64>,
+    profit_buy_order_per_unit: Option<f64>,
+    profit_buy_order_daily: Option<f64>,
+    margin_buy_order: Option<f64>,
+    money_freeze_buy_order: Option<f64>,
+    freeze_rate: Option<f64>,
+    abroad_buy_taxed: Option<f64>,
+    profit_instant_buy_per_unit: Option<f64>,
+    profit_instant_buy_daily: Option<f64>,
+    margin_instant_buy: Option<f64>,
+    money_freeze_instant_buy: Option<f64>,
+    freeze_rate_instant_buy: Option<f64>,
+    profit_instant_sell_per_unit: Option<f64>,
+    profit_instant_sell_daily: Option<f64>,
+    margin_instant_sell: Option<f64>,
+    money_freeze_instant_sell: Option<f64>,
+    freeze_rate_instant_sell: Option<f64>,
+    /// Every destination in config order, the best one included.
+    destinations: Vec<DestinationSummary>,
+}
+
+/// One destination's numbers for the buy order mode, supplied from its cheapest source.
+#[derive(Debug, PartialEq, Clone, Default, FieldNamesAsSlice, Deserialize, Serialize)]
+pub struct DestinationSummary {
+    name: String,
+    source: String,
+    landed_cost: Option<f64>,
+    not_seeded: bool,
+    sell_taxed: Option<f64>,
+    avg_daily: Option<f64>,
+    profit_daily: Option<f64>,
+    margin: Option<f64>,
+    freeze_rate: Option<f64>,
+}
+
+/// How an item changes hands at either end. Orders are patient and pay the broker fee,
+/// taking the other side's orders is instant and skips it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeMode {
+    /// Buy order at the source at `buy_max`, sell order abroad at `sell_min`.
+    BuyOrder,
+    /// Take the source's sell orders at `sell_min`, sell order abroad at `sell_min`.
+    InstantBuy,
+    /// Buy order at the source at `buy_max`, dump into abroad buy orders at `buy_max`.
+    InstantSell,
 }
 
 impl ItemData {
-    pub fn get_shipping_price(&self) -> f64 {
-        let shipping_price = &self.type_volume * DELIVERY_PRICE_PER_CUBOMETR;
-        return shipping_price as f64;
+    /// Volume freight plus the collateral fee on what the unit cost at the source.
+    pub fn get_shipping_price(
+        &self,
+        mode: TradeMode,
+        source: &Source,
+        destination: &Destination,
+    ) -> f64 {
+        let freight = source.freight_to(destination);
+        let volume_price = self.type_volume as f64 * freight.price_per_m3;
+        let cost = self.get_source_cost(mode, source).unwrap_or(0.0);
+        let collateral = cost * freight.collateral_rate;
+        return volume_price + collateral;
     }
-    pub fn get_jita_buy_price_with_tax(&self) -> f64 {
-        let jtd = &self.jita_trade_data.as_ref().unwrap();
-        return jtd.buy_max * JITA_TAXRATE + jtd.buy_max;
+    /// `None` when nobody buys at the source, there is no order to outbid.
+    pub fn get_source_buy_price_with_tax(&self, source: &Source) -> Option<f64> {
+        let std = self.trade_data_at(source.location_id)?;
+        if std.buy_listed <= 0 {
+            return None;
+        }
+        Some(std.buy_max * source.broker_fee + std.buy_max)
     }
-    pub fn get_abroad_stocked_ratio(&self) -> f64 {
-        let abtd = &self.abroad_trade_data.as_ref().unwrap();
-        return abtd.sell_listed as f64 / abtd.weekly_movement;
+    /// `None` when nobody sells at the source. Taking an order pays no broker fee.
+    pub fn get_source_sell_price(&self, source: &Source) -> Option<f64> {
+        let std = self.trade_data_at(source.location_id)?;
+        if std.sell_listed <= 0 {
+            return None;
+        }
+        Some(std.sell_min)
     }
-
-    pub fn get_abroad_sell_taxed(&self) -> f64 {
-        let abtd = &self.abroad_trade_data.as_ref().unwrap();
-        return abtd.sell_min - abtd.sell_min * ABROAD_TAX_VALUE;
+    pub fn get_source_cost(&self, mode: TradeMode, source: &Source) -> Option<f64> {
+        match mode {
+            TradeMode::BuyOrder | TradeMode::InstantSell => {
+                self.get_source_buy_price_with_tax(source)
+            }
+            TradeMode::InstantBuy => self.get_source_sell_price(source),
+        }
+    }
+    /// What a unit bought with a buy order at `source` costs once it is at `destination`.
+    pub fn get_landed_cost(&self, source: &Source, destination: &Destination) -> Option<f64> {
+        let shipping = self.get_shipping_price(TradeMode::BuyOrder, source, destination);
+        Some(self.get_source_buy_price_with_tax(source)? + shipping)
+    }
+    /// `None` when nothing traded abroad last week, the ratio would be infinite.
+    pub fn get_abroad_stocked_ratio(&self, destination: &Destination) -> Option<f64> {
+        let abtd = self.trade_data_at(destination.location_id)?;
+        if abtd.weekly_movement <= 0.0 {
+            return None;
+        }
+        return Some(abtd.sell_listed as f64 / abtd.weekly_movement);
     }
-    pub fn get_abroad_avg_daily(&self) -> f64 {
-        let abtd = &self.abroad_trade_data.as_ref().unwrap();
-        let abstocked = &self.get_abroad_stocked_ratio();
-        return abtd.weekly_movement / 7.0 / f64::sqrt(*abstocked);
+    /// History-based when asked for and ingested, so markets priced from an ESI order book,
+    /// which carries no traded volume, still get a ratio.
+    pub fn get_abroad_stocked_ratio_estimate(
+        &self,
+        estimate: DailyVolumeEstimate,
+        destination: &Destination,
+    ) -> Option<f64> {
+        let from_history = || {
+            let abtd = self.trade_data_at(destination.location_id)?;
+            let daily = self.get_abroad_avg_daily_from_history(destination)?;
+            (daily > 0.0).then(|| abtd.sell_listed as f64 / (daily * 7.0))
+        };
+        match estimate {
+            DailyVolumeEstimate::History => {
+                from_history().or_else(|| self.get_abroad_stocked_ratio(destination))
+            }
+            DailyVolumeEstimate::Heuristic => self.get_abroad_stocked_ratio(destination),
+        }
     }
-    pub fn get_profit_jita_buy_per_unit(&self) -> f64 {
-        return &self.get_abroad_sell_taxed()
-            - &self.get_jita_buy_price_with_tax()
-            - &self.get_shipping_price();
+    /// `None` when nobody sells abroad, there is no price to undercut.
+    pub fn get_abroad_sell_taxed(&self, destination: &Destination) -> Option<f64> {
+        let abtd = self.trade_data_at(destination.location_id)?;
+        if abtd.sell_listed <= 0 {
+            return None;
+        }
+        return Some(abtd.sell_min - abtd.sell_min * destination.sell_fee());
     }
-    pub fn get_profit_jita_buy_daily(&self) -> f64 {
-        return &self.get_abroad_avg_daily() * &self.get_profit_jita_buy_per_unit();
+    /// `None` when nobody buys abroad. Filling a buy order only pays sales tax.
+    pub fn get_abroad_buy_taxed(&self, destination: &Destination) -> Option<f64> {
+        let abtd = self.trade_data_at(destination.location_id)?;
+        if abtd.buy_listed <= 0 {
+            return None;
+        }
+        Some(abtd.buy_max - abtd.buy_max * destination.sales_tax)
     }
-    pub fn get_margin_jita_buy(&self) -> f64 {
-        return &self.get_profit_jita_buy_per_unit()
-            / (&self.get_jita_buy_price_with_tax() + &self.get_shipping_price());
+    pub fn get_abroad_revenue(&self, mode: TradeMode, destination: &Destination) -> Option<f64> {
+        match mode {
+            TradeMode::BuyOrder | TradeMode::InstantBuy => self.get_abroad_sell_taxed(destination),
+            TradeMode::InstantSell => self.get_abroad_buy_taxed(destination),
+        }
+    }
+    pub fn get_abroad_avg_daily(&self, destination: &Destination) -> Option<f64> {
+        let abtd = self.trade_data_at(destination.location_id)?;
+        let abstocked = self.get_abroad_stocked_ratio(destination)?;
+        return Some(abtd.weekly_movement / 7.0 / f64::sqrt(abstocked));
     }
-    pub fn get_money_freeze_buy(&self) -> f64 {
-        return &self.get_abroad_avg_daily() * &self.get_jita_buy_price_with_tax();
+    pub fn get_abroad_avg_daily_from_history(&self, destination: &Destination) -> Option<f64> {
+        return self
+            .history_at(destination.location_id)
+            .map(|h| h.avg_daily_volume);
     }
-    pub fn get_freeze_rate(&self) -> f64 {
-        return &self.get_profit_jita_buy_daily() / &self.get_money_freeze_buy();
+    /// History-based volume when asked for and ingested, the stocked-ratio guess otherwise.
+    pub fn get_abroad_avg_daily_estimate(
+        &self,
+        estimate: DailyVolumeEstimate,
+        destination: &Destination,
+    ) -> Option<f64> {
+        match estimate {
+            DailyVolumeEstimate::History => self
+                .get_abroad_avg_daily_from_history(destination)
+                .or_else(|| self.get_abroad_avg_daily(destination)),
+            DailyVolumeEstimate::Heuristic => self.get_abroad_avg_daily(destination),
+        }
+    }
+    pub fn get_profit_per_unit(
+        &self,
+        mode: TradeMode,
+        source: &Source,
+        destination: &Destination,
+    ) -> Option<f64> {
+        return Some(
+            self.get_abroad_revenue(mode, destination)?
+                - self.get_source_cost(mode, source)?
+                - self.get_shipping_price(mode, source, destination),
+        );
+    }
+    /// Every mode moves the same estimated daily volume, only the price per unit differs.
+    pub fn get_profit_daily(
+        &self,
+        mode: TradeMode,
+        estimate: DailyVolumeEstimate,
+        source: &Source,
+        destination: &Destination,
+    ) -> Option<f64> {
+        return Some(
+            self.get_abroad_avg_daily_estimate(estimate, destination)?
+                * self.get_profit_per_unit(mode, source, destination)?,
+        );
+    }
+    pub fn get_margin(
+        &self,
+        mode: TradeMode,
+        source: &Source,
+        destination: &Destination,
+    ) -> Option<f64> {
+        return Some(
+            self.get_profit_per_unit(mode, source, destination)?
+                / (self.get_source_cost(mode, source)?
+                    + self.get_shipping_price(mode, source, destination)),
+        );
+    }
+    pub fn get_money_freeze(
+        &self,
+        mode: TradeMode,
+        estimate: DailyVolumeEstimate,
+        source: &Source,
+        destination: &Destination,
+    ) -> Option<f64> {
+        return Some(
+            self.get_abroad_avg_daily_estimate(estimate, destination)?
+                * self.get_source_cost(mode, source)?,
+        );
+    }
+    pub fn get_freeze_rate(
+        &self,
+        mode: TradeMode,
+        estimate: DailyVolumeEstimate,
+        source: &Source,
+        destination: &Destination,
+    ) -> Option<f64> {
+        let money_freeze = self.get_money_freeze(mode, estimate, source, destination)?;
+        if money_freeze == 0.0 {
+            return None;
+        }
+        return Some(self.get_profit_daily(mode, estimate, source, destination)? / money_freeze);
+    }
+    /// Bought at the source but nobody sells it abroad: no margin to compute, but an
+    /// empty market is an opportunity on its own.
+    pub fn is_not_seeded_abroad(&self, source: &Source, destination: &Destination) -> bool {
+        let has_source_sellers = self
+            .trade_data_at(source.location_id)
+            .is_some_and(|std| std.sell_listed > 0 || std.buy_listed > 0);
+        let has_abroad_sellers = self
+            .trade_data_at(destination.location_id)
+            .is_some_and(|abtd| abtd.sell_listed > 0);
+        return has_source_sellers && !has_abroad_sellers;
     }
 }
 
 impl ExtendedItemData {
-    fn new(data: ItemData) -> Self {
-        let shipping_price = data.get_shipping_price();
-        let jtd = data.jita_trade_data.clone().unwrap();
-        let atd = data.abroad_trade_data.clone().unwrap();
+    /// The table rows for `items`, leaving out stale quotes when `hide_stale` is set.
+    fn from_items(
+        items: &[ItemData],
+        estimate: DailyVolumeEstimate,
+        max_quote_age: chrono::TimeDelta,
+        hide_stale: bool,
+        config: &Config,
+    ) -> Vec<Self> {
+        items
+            .iter()
+            .map(|item| ExtendedItemData::new(item.clone(), estimate, max_quote_age, config))
+            .filter(|item| !(hide_stale && item.stale_quote))
+            .collect()
+    }
+
+    /// Priced at every destination, each supplied from its cheapest source. The row
+    /// carries the numbers of the best destination.
+    fn new(
+        data: ItemData,
+        estimate: DailyVolumeEstimate,
+        max_quote_age: chrono::TimeDelta,
+        config: &Config,
+    ) -> Self {
+        let sources = config.sources();
+        let mut at_each: Vec<Self> = config
+            .destinations()
+            .iter()
+            .map(|destination| {
+                let source = cheapest_source(&data, &sources, destination);
+                ExtendedItemData::on_route(&data, source, destination, estimate, max_quote_age)
+            })
+            .collect();
+        let destinations = at_each.iter().map(DestinationSummary::of).collect();
+        let best = best_destination(&at_each);
+        ExtendedItemData {
+            destinations,
+            ..at_each.swap_remove(best)
+        }
+    }
+
+    fn on_route(
+        data: &ItemData,
+        source: &Source,
+        destination: &Destination,
+        estimate: DailyVolumeEstimate,
+        max_quote_age: chrono::TimeDelta,
+    ) -> Self {
+        let shipping_price = data.get_shipping_price(TradeMode::BuyOrder, source, destination);
+        let std = data.trade_data_at(source.location_id).cloned();
+        let atd = data.trade_data_at(destination.location_id).cloned();
+        let history = data.history_at(destination.location_id);
         let id = data.type_id;
         let name = data.type_name.to_owned();
         let volume = data.type_volume;
-        let jtb_with_tax = data.get_jita_buy_price_with_tax();
-        let abroad_stocked_ratio = data.get_abroad_stocked_ratio();
-        let abroad_sell_taxed = data.get_abroad_sell_taxed();
-        let abroad_avg_daily = data.get_abroad_avg_daily();
-        let profit_jita_buy_per_unit = data.get_profit_jita_buy_per_unit();
-        let profit_jita_buy_daily = data.get_profit_jita_buy_daily();
-        let margin_jita_buy = data.get_margin_jita_buy();
-        let money_freeze_buy = data.get_money_freeze_buy();
-        let freeze_rate = data.get_freeze_rate();
-
-        // TODO: Add filters to display only good stuff
+        let not_seeded_abroad = data.is_not_seeded_abroad(source, destination);
+        let stale_quote = data.has_stale_quote(max_quote_age, chrono::Utc::now());
+        let stb_with_tax = data.get_source_buy_price_with_tax(source);
+        let abroad_stocked_ratio = data.get_abroad_stocked_ratio_estimate(estimate, destination);
+        let abroad_sell_taxed = data.get_abroad_sell_taxed(destination);
+        let abroad_avg_daily = data.get_abroad_avg_daily_estimate(estimate, destination);
+        let abroad_median_price = history.map(|h| h.median_price);
+        let abroad_volatility = history.map(|h| h.volatility);
+        let buy_order = TradeMode::BuyOrder;
+        let profit_buy_order_per_unit = data.get_profit_per_unit(buy_order, source, destination);
+        let profit_buy_order_daily =
+            data.get_profit_daily(buy_order, estimate, source, destination);
+        let margin_buy_order = data.get_margin(buy_order, source, destination);
+        let money_freeze_buy_order =
+            data.get_money_freeze(buy_order, estimate, source, destination);
+        let freeze_rate = data.get_freeze_rate(buy_order, estimate, source, destination);
+        let instant_buy = TradeMode::InstantBuy;
+        let instant_sell = TradeMode::InstantSell;
+
+        // Which of these are worth showing is up to `filters::OpportunityFilter`.
         ExtendedItemData {
             type_id: id,
             type_volume: volume,
             type_name: name,
-            jita_trade_data: jtd,
-            jita_buy_with_tax: jtb_with_tax,
+            market_group_id: data.market_group_id,
+            metadata: data.metadata.clone(),
+            source: source.name.clone(),
+            destination: destination.name.clone(),
+            not_seeded_abroad,
+            stale_quote,
+            source_trade_data: std,
+            source_buy_with_tax: stb_with_tax,
             abroad_trade_data: atd,
-            abroad_stocked_ratio: abroad_stocked_ratio,
-            shipping_price: shipping_price,
-            abroad_sell_taxed: abroad_sell_taxed,
-            abroad_avg_daily: abroad_avg_daily,
-            profit_jita_buy_per_unit: profit_jita_buy_per_unit,
-            profit_jita_buy_daily: profit_jita_buy_daily,
-            margin_jita_buy: margin_jita_buy,
-            money_freeze_buy: money_freeze_buy,
-            freeze_rate: freeze_rate,
+            abroad_stocked_ratio,
+            shipping_price,
+            landed_cost: data.get_landed_cost(source, destination),
+            abroad_sell_taxed,
+            abroad_avg_daily,
+            abroad_median_price,
+            abroad_volatility,
+            profit_buy_order_per_unit,
+            profit_buy_order_daily,
+            margin_buy_order,
+            money_freeze_buy_order,
+            freeze_rate,
+            abroad_buy_taxed: data.get_abroad_buy_taxed(destination),
+            profit_instant_buy_per_unit: data.get_profit_per_unit(instant_buy, source, destination),
+            profit_instant_buy_daily: data
+                .get_profit_daily(instant_buy, estimate, source, destination),
+            margin_instant_buy: data.get_margin(instant_buy, source, destination),
+            money_freeze_instant_buy: data
+                .get_money_freeze(instant_buy, estimate, source, destination),
+            freeze_rate_instant_buy: data
+                .get_freeze_rate(instant_buy, estimate, source, destination),
+            profit_instant_sell_per_unit: data
+                .get_profit_per_unit(instant_sell, source, destination),
+            profit_instant_sell_daily: data
+                .get_profit_daily(instant_sell, estimate, source, destination),
+            margin_instant_sell: data.get_margin(instant_sell, source, destination),
+            money_freeze_instant_sell: data
+                .get_money_freeze(instant_sell, estimate, source, destination),
+            freeze_rate_instant_sell: data
+                .get_freeze_rate(instant_sell, estimate, source, destination),
+            destinations: vec![],
         }
     }
 }
 
+impl DestinationSummary {
+    fn of(row: &ExtendedItemData) -> Self {
+        DestinationSummary {
+            name: row.destination.clone(),
+            source: row.source.clone(),
+            landed_cost: row.landed_cost,
+            not_seeded: row.not_seeded_abroad,
+            sell_taxed: row.abroad_sell_taxed,
+            avg_daily: row.abroad_avg_daily,
+            profit_daily: row.profit_buy_order_daily,
+            margin: row.margin_buy_order,
+            freeze_rate: row.freeze_rate,
+        }
+    }
+}
+
+/// The lowest landed cost wins, the first source on a tie. An item no source has buy
+/// orders for is priced from the first source, where it shows up without a cost.
+fn cheapest_source<'a>(
+    data: &ItemData,
+    sources: &'a [Source],
+    destination: &Destination,
+) -> &'a Source {
+    sources
+        .iter()
+        .filter_map(|source| Some((source, data.get_landed_cost(source, destination)?)))
+        .reduce(|cheapest, other| if other.1 < cheapest.1 { other } else { cheapest })
+        .map_or(&sources[0], |(source, _)| source)
+}
+
+/// The highest daily profit wins, the first destination on a tie. Where nothing turns a
+/// profit, a destination nobody sells at is still worth seeding.
+fn best_destination(at_each: &[ExtendedItemData]) -> usize {
+    at_each
+        .iter()
+        .enumerate()
+        .filter_map(|(i, row)| Some((i, row.profit_buy_order_daily?)))
+        .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(i, _)| i)
+        .or_else(|| at_each.iter().position(|row| row.not_seeded_abroad))
+        .unwrap_or(0)
+}
+
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
-    // let names: Vec<&str> = vec!["Tritanium", "Buzzard", "Hulk"];
-    // TODO: hardcode names cant work with 16k strings
+    let cli = Cli::parse();
+    let (base_config, config_path) = Config::load_or_create(cli.config.clone())?;
+    let config = match &cli.profile {
+        Some(name) => base_config.profile(name)?,
+        None => base_config.clone(),
+    };
+    println!("Using config {:?}, profile {:?}", config_path, cli.profile);
+    if let Some(command) = &cli.command {
+        return run_command(command, &cli, &config);
+    }
     // TODO: filter out items not interesting for trade dunno how
-    let names: Vec<String> = get_tradable_item_names_from_db();
-    
-    let items_data: &Vec<ItemData> = &get_item_data_from_db(names);
+    let sde = Sde::open_resolved(cli.sde_path.clone(), config.sde.path.clone())?;
+    println!("Using {}", sde.describe());
+
+    let market_group_paths = if cli.market_groups.is_empty() {
+        &config.items.market_groups
+    } else {
+        &cli.market_groups
+    };
+    // The tree is only required to resolve market group paths, the UI just loses its group filter.
+    let market_groups = if market_group_paths.is_empty() {
+        MarketGroupTree::load(&sde)
+            .map_err(|e| println!("Market group filter unavailable: {}", e))
+            .ok()
+    } else {
+        Some(MarketGroupTree::load(&sde)?)
+    };
+    let mut type_ids = cli.type_ids.clone();
+    if let Some(tree) = market_groups.as_ref().filter(|_| !market_group_paths.is_empty()) {
+        let group_ids = market_group_paths
+            .iter()
+            .map(|path| tree.find_by_path(path))
+            .collect::<datagetter::datagetter::Result<Vec<i32>>>()?;
+        type_ids.extend(get_type_ids_in_market_groups(&sde, tree, &group_ids)?);
+    }
+    let selection = if type_ids.is_empty() {
+        SdeItemSelection::Tradable
+    } else {
+        SdeItemSelection::TypeIds(type_ids)
+    };
+    let metadata_filter = cli.item_metadata_filter(config.items.metadata_filter());
+    let items_data: &Vec<ItemData> = &get_item_data_from_db(&sde, &selection)?
+        .into_values()
+        .filter(|item| metadata_filter.matches(&item.metadata))
+        .collect();
+    if items_data.is_empty() {
+        return Err(format!("no items left after filtering by {:?}", metadata_filter).into());
+    }
     println!("Bulk from db:\n{:?}", items_data);
 
     let item_ids: &Vec<i32> = &items_data.into_iter().map(|item| item.type_id).collect();
     println!("IDIS:\n{:?}", item_ids);
 
-    let jita_id = "60003760";
-    let goon_keep_id = "1030049082711";
+    let sources = config.sources();
+    let destinations = config.destinations();
+    // Exports are priced on the reversed routes, but every market is still fetched in the role
+    // it has in the config, so the keepstar keeps its structure provider.
+    let analysed = if cli.reverse { config.reversed() } else { config.clone() };
+    let exits = if cli.reverse { analysed.destinations() } else { vec![] };
+    let mut hubs: Vec<(&str, i64, Option<i32>)> = vec![];
+    for (name, location_id, region_id) in sources
+        .iter()
+        .map(|s| (s.name.as_str(), s.location_id, s.region_id))
+        .chain(exits.iter().map(|e| (e.name.as_str(), e.location_id, e.region_id)))
+    {
+        if hubs.iter().all(|hub| hub.1 != location_id) {
+            hubs.push((name, location_id, region_id));
+        }
+    }
+
+    let fetch_options = FetchOptions {
+        max_concurrent_requests: cli.max_concurrent_requests,
+        request_timeout: std::time::Duration::from_secs(cli.request_timeout_secs),
+        retry: RetryPolicy {
+            max_retries: cli.max_retries,
+            ..RetryPolicy::default()
+        },
+    };
+    // One budget for every provider, so parallel Jita and abroad fetches share it.
+    let rate_limiter = Arc::new(RateLimiter::new(cli.requests_per_second, cli.max_concurrent_requests as u32));
+    let http = HttpClient::new(&fetch_options, rate_limiter)?;
+    // Every structure market shares one login. Offline runs only read the cache and need none.
+    let sso = match cli.abroad_source {
+        AbroadPriceSource::EsiStructure if !cli.offline => {
+            let client_id = cli
+                .sso_client_id
+                .clone()
+                .ok_or("structure markets need --sso-client-id or EVE_SSO_CLIENT_ID")?;
+            let sso_config = SsoConfig {
+                request_timeout: fetch_options.request_timeout,
+                ..SsoConfig::new(&client_id)
+            };
+            let sso = SsoClient::new(sso_config, TokenStore::default_location())?;
+            if !sso.has_refresh_token() {
+                sso.login().await?;
+            }
+            Some(Arc::new(sso))
+        }
+        _ => None,
+    };
+    // A hub that is also a destination is fetched once, with the destination's provider.
+    let destination_ids: BTreeSet<i64> = destinations.iter().map(|d| d.location_id).collect();
+    let mut markets: Vec<(&str, i64, Box<dyn MarketDataProvider>)> = vec![];
+    for (name, location_id, region_id) in hubs.iter().filter(|h| !destination_ids.contains(&h.1)) {
+        let provider: Box<dyn MarketDataProvider> = match cli.jita_source {
+            PriceSource::Goonmetrics => Box::new(GoonmetricsProvider::new(
+                &location_id.to_string(),
+                http.clone(),
+                fetch_options.max_concurrent_requests,
+            )),
+            PriceSource::Esi => {
+                let region_id = region_id.ok_or_else(|| {
+                    format!("ESI prices for source {:?} need its region_id", name)
+                })?;
+                Box::new(EsiMarketProvider::new(
+                    region_id,
+                    *location_id,
+                    http.clone(),
+                    fetch_options.max_concurrent_requests,
+                ))
+            }
+        };
+        markets.push((name, *location_id, provider));
+    }
+    for destination in &destinations {
+        let provider: Box<dyn MarketDataProvider> = match (cli.abroad_source, &sso) {
+            (AbroadPriceSource::Goonmetrics, _) => Box::new(GoonmetricsProvider::new(
+                &destination.location_id.to_string(),
+                http.clone(),
+                fetch_options.max_concurrent_requests,
+            )),
+            (AbroadPriceSource::EsiStructure, Some(sso)) => {
+                Box::new(EsiStructureMarketProvider::new(
+                    destination.location_id,
+                    sso.clone(),
+                    http.clone(),
+                    fetch_options.max_concurrent_requests,
+                ))
+            }
+            (AbroadPriceSource::EsiStructure, None) => Box::new(
+                EsiStructureMarketProvider::offline(destination.location_id, http.clone()),
+            ),
+        };
+        markets.push((&destination.name, destination.location_id, provider));
+    }
 
-    let jita_trade_data = get_item_data_from_api(&jita_id, &item_ids).await;
-    println!("JITA TRADE DATA:\n{:?}", jita_trade_data);
+    let price_cache = Arc::new(Mutex::new(PriceCache::open_default()?));
+    let cache_ttl = chrono::Duration::minutes(cli.cache_ttl_minutes);
+    let providers: Vec<(&str, i64, CachedProvider)> = markets
+        .into_iter()
+        .map(|(market, location_id, provider)| {
+            let cache = price_cache.clone();
+            (market, location_id, CachedProvider::new(provider, cache, cache_ttl, cli.offline))
+        })
+        .collect();
 
-    let goon_trade_data = get_item_data_from_api(&goon_keep_id, &item_ids).await;
-    println!("GOON TRADE DATA:\n{:?}", goon_trade_data);
+    let mut reports = futures::future::join_all(
+        providers
+            .iter()
+            .map(|(_, _, provider)| provider.get_trade_data_report(item_ids)),
+    )
+    .await;
+    for ((_, _, provider), report) in providers.iter().zip(reports.iter_mut()) {
+        println!("{}: {}", provider.name(), report.summary());
+        if !report.is_complete() && ask_retry_failed_batches() {
+            let retry = provider
+                .get_trade_data_report(&report.failed_type_ids())
+                .await;
+            report.merge_retry(retry);
+            println!("{}: {}", provider.name(), report.summary());
+        }
+    }
+    let fetch_summary: Vec<String> = providers
+        .iter()
+        .zip(&reports)
+        .map(|((market, _, provider), report)| {
+            format!("{} ({}): {}", market, provider.name(), report.summary())
+        })
+        .collect();
+    let trade_data: BTreeMap<i64, TradeDataByType> = providers
+        .iter()
+        .zip(reports)
+        .map(|((market, location_id, provider), report)| {
+            log::debug!("{} trade data ({}): {:?}", market, provider.name(), report.data);
+            (*location_id, report.data)
+        })
+        .collect();
 
-    let merged_trade_data = merge_trade_data(
-        &items_data,
-        &jita_trade_data.expect("hui"),
-        &goon_trade_data.expect("hui"),
-    );
-    println!("MERGED:\n{:?}", merged_trade_data);
+    let mut merged_trade_data = merge_trade_data(&items_data, &trade_data);
+    log::debug!("merged trade data: {:?}", merged_trade_data);
+
+    // ESI order books carry no traded volume, only market history has it.
+    let esi_priced = |location_id: &i64| {
+        if destination_ids.contains(location_id) {
+            cli.abroad_source == AbroadPriceSource::EsiStructure
+        } else {
+            cli.jita_source == PriceSource::Esi
+        }
+    };
+    let sold_at = analysed.destinations();
+    let daily_volume = if sold_at.iter().any(|d| esi_priced(&d.location_id)) {
+        if cli.daily_volume == DailyVolumeEstimate::Heuristic {
+            println!(
+                "HISTORY: ESI prices carry no traded volume, estimating it from market history"
+            );
+        }
+        DailyVolumeEstimate::History
+    } else {
+        cli.daily_volume
+    };
 
-    let mut extended_data_collection = vec![];
-    for ele in merged_trade_data {
-        let extended_item_data = ExtendedItemData::new(ele.to_owned());
-        extended_data_collection.push(extended_item_data);
+    if daily_volume == DailyVolumeEstimate::History {
+        let mut history_store = HistoryStore::open_default()?;
+        let mut ingested_regions = BTreeSet::new();
+        for destination in &sold_at {
+            let region_id = destination.region_id.or(cli.abroad_region_id).ok_or_else(|| {
+                format!(
+                    "history-based daily volume needs a region for destination {:?}, \
+                    set its region_id or --abroad-region-id",
+                    destination.name
+                )
+            })?;
+            if !cli.offline && ingested_regions.insert(region_id) {
+                let report = ingest_market_history_from_esi(
+                    &http,
+                    &mut history_store,
+                    region_id,
+                    item_ids,
+                    fetch_options.max_concurrent_requests,
+                )
+                .await?;
+                println!("HISTORY: refreshed {} types in region {}", report.data, region_id);
+                if let Some(failed) = report.failed_batches.first() {
+                    println!(
+                        "HISTORY: {} types without history in region {}, first cause: {}",
+                        report.missing_type_count(),
+                        region_id,
+                        failed.cause
+                    );
+                }
+            }
+            let stats = history_store.get_stats_by_type(region_id, item_ids, cli.history_days)?;
+            attach_history_stats(&mut merged_trade_data, destination.location_id, &stats);
+        }
     }
 
+    let max_quote_age = chrono::TimeDelta::minutes(cli.max_quote_age_minutes);
+    let extended_data_collection = ExtendedItemData::from_items(
+        &merged_trade_data,
+        daily_volume,
+        max_quote_age,
+        cli.hide_stale,
+        &analysed,
+    );
+
     println!("EXTENDED DATA! \n {:?}", extended_data_collection);
 
+    let opportunity_filter = cli.opportunity_filter(&config.thresholds);
+    let (_, opportunity_report) = opportunity_filter.apply(&extended_data_collection);
+    for line in opportunity_report.lines() {
+        println!("OPPORTUNITIES: {}", line);
+    }
+
     let item_view_manager = TradeItemViewManager::new(TradeItemViewManagerInitData {
         items: extended_data_collection,
+        fetch_summary,
+        max_quote_age,
+        market_groups,
+        metadata_filter,
+        opportunity_filter,
+        profile_switch: Some(ProfileSwitch {
+            base_config,
+            active_profile: cli.profile.clone(),
+            fetched_markets: analysed.market_locations(),
+            trade_items: merged_trade_data,
+            daily_volume,
+            hide_stale: cli.hide_stale,
+            reverse: cli.reverse,
+        }),
     });
     // UI
     match render_ui(item_view_manager) {
@@ -180,9 +767,47 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn run_command(command: &Command, cli: &Cli, config: &Config) -> Result<()> {
+    let (target, source) = resolve_sde_path(cli.sde_path.clone(), config.sde.path.clone());
+    match command {
+        Command::ImportSde {
+            source: dump,
+            sde_version,
+        } => {
+            let import = import_sde(dump, &target, sde_version.clone())?;
+            println!("Installed {} at {:?} ({})", import.version, import.installed, source);
+            if let Some(previous) = import.previous {
+                println!("Previous SDE kept at {:?}, `rollback-sde` restores it", previous);
+            }
+        }
+        Command::RollbackSde => {
+            rollback_sde(&target)?;
+            println!("Restored {}", Sde::open(&target)?.describe());
+        }
+    }
+    Ok(())
+}
+
+/// Only asks when someone can answer, so piped or scheduled runs keep the partial result.
+fn ask_retry_failed_batches() -> bool {
+    use std::io::{BufRead, IsTerminal, Write};
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    print!("Retry failed batches? [y/N] ");
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::datagetter::datagetter::trade_data_by_type;
+    use crate::datagetter::tests::markets;
     use crate::goonmetrics::goonmetrics::*;
     use crate::ui::ui::FormatForDisplay;
     #[test]
@@ -192,15 +817,17 @@ mod tests {
                 type_id: 34,
                 type_volume: 0.01,
                 type_name: "Tritanium".to_string(),
-                jita_trade_data: None,
-                abroad_trade_data: None,
+                markets: markets(None, None),
+                market_group_id: None,
+                metadata: ItemMetadata::default(),
             },
             ItemData {
                 type_id: 11192,
                 type_volume: 19400.0,
                 type_name: "Buzzard".to_string(),
-                jita_trade_data: None,
-                abroad_trade_data: None,
+                markets: markets(None, None),
+                market_group_id: None,
+                metadata: ItemMetadata::default(),
             },
         ]
         .to_vec();
@@ -209,32 +836,32 @@ mod tests {
             types: [
                 Types::Type(ItemType {
                     id: 34,
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     all: All {
-                        weekly_movement: "3".to_string(),
+                        weekly_movement: Some(3.0),
                     },
                     buy: Buy {
-                        listed: "3".to_string(),
-                        max: "3".to_string(),
+                        listed: Some(3),
+                        max: Some(3.0),
                     },
                     sell: Sell {
-                        listed: "3".to_string(),
-                        min: "3".to_string(),
+                        listed: Some(3),
+                        min: Some(3.0),
                     },
                 }),
                 Types::Type(ItemType {
                     id: 11192,
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     all: All {
-                        weekly_movement: "3".to_string(),
+                        weekly_movement: Some(3.0),
                     },
                     buy: Buy {
-                        listed: "3".to_string(),
-                        max: "3".to_string(),
+                        listed: Some(3),
+                        max: Some(3.0),
                     },
                     sell: Sell {
-                        listed: "3".to_string(),
-                        min: "3".to_string(),
+                        listed: Some(3),
+                        min: Some(3.0),
                     },
                 }),
             ]
@@ -246,32 +873,32 @@ mod tests {
             types: [
                 Types::Type(ItemType {
                     id: 34,
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     all: All {
-                        weekly_movement: "3".to_string(),
+                        weekly_movement: Some(3.0),
                     },
                     buy: Buy {
-                        listed: "3".to_string(),
-                        max: "3".to_string(),
+                        listed: Some(3),
+                        max: Some(3.0),
                     },
                     sell: Sell {
-                        listed: "3".to_string(),
-                        min: "3".to_string(),
+                        listed: Some(3),
+                        min: Some(3.0),
                     },
                 }),
                 Types::Type(ItemType {
                     id: 11192,
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     all: All {
-                        weekly_movement: "3".to_string(),
+                        weekly_movement: Some(3.0),
                     },
                     buy: Buy {
-                        listed: "3".to_string(),
-                        max: "3".to_string(),
+                        listed: Some(3),
+                        max: Some(3.0),
                     },
                     sell: Sell {
-                        listed: "3".to_string(),
-                        min: "3".to_string(),
+                        listed: Some(3),
+                        min: Some(3.0),
                     },
                 }),
             ]
@@ -284,50 +911,60 @@ mod tests {
                 type_id: 34,
                 type_volume: 0.01,
                 type_name: "Tritanium".to_string(),
-                jita_trade_data: Some(TradeData {
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                markets: markets(Some(TradeData {
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     weekly_movement: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_max: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
                     sell_min: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     sell_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
-                }),
-                abroad_trade_data: Some(TradeData {
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                }), Some(TradeData {
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     weekly_movement: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_max: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
                     sell_min: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     sell_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
-                }),
+                })),
+                market_group_id: None,
+                metadata: ItemMetadata::default(),
             },
             ItemData {
                 type_id: 11192,
                 type_volume: 19400.0,
                 type_name: "Buzzard".to_string(),
-                jita_trade_data: Some(TradeData {
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                markets: markets(Some(TradeData {
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     weekly_movement: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_max: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
                     sell_min: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     sell_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
-                }),
-                abroad_trade_data: Some(TradeData {
-                    updated: "2024-05-03T13:36:22Z".to_string(),
+                }), Some(TradeData {
+                    updated: "2024-05-03T13:36:22Z".parse().ok(),
                     weekly_movement: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_max: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     buy_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
                     sell_min: "3".to_string().parse::<f64>().expect("CANT PARSE!"),
                     sell_listed: "3".to_string().parse::<i64>().expect("CANT PARSE!"),
-                }),
+                })),
+                market_group_id: None,
+                metadata: ItemMetadata::default(),
             },
         ];
 
         let actual_merge_result = merge_trade_data(
             items_data,
-            &mock_jita_trade_data.expect("aaa"),
-            &mock_goon_trade_data.expect("aaa"),
+            &BTreeMap::from([
+                (
+                    config::config::StationsConfig::default().jita_station_id,
+                    trade_data_by_type(&mock_jita_trade_data.unwrap()),
+                ),
+                (
+                    config::config::StationsConfig::default().abroad_location_id,
+                    trade_data_by_type(&mock_goon_trade_data.unwrap()),
+                ),
+            ]),
         );
 
         assert_eq!(desired_merge_result, actual_merge_result);
@@ -335,63 +972,540 @@ mod tests {
 
     #[test]
     fn calculate_fields() {
+        let config = Config::default();
+        let source = &config.sources()[0];
+        let destination = &config.destinations()[0];
         let mock_item = ItemData {
             type_id: 11192,
             type_volume: 2500.0,
             type_name: "Buzzard".to_owned(),
-            jita_trade_data: Some(TradeData {
-                updated: "2024-08-21T16:16:48Z".to_owned(),
+            markets: markets(Some(TradeData {
+                updated: "2024-08-21T16:16:48Z".parse().ok(),
                 weekly_movement: 865.2,
                 buy_max: 10_000_000.0,
                 buy_listed: 138,
                 sell_min: 23200000.0,
                 sell_listed: 758,
-            }),
-            abroad_trade_data: Some(TradeData {
-                updated: "2024-08-21T16:15:35Z".to_owned(),
+            }), Some(TradeData {
+                updated: "2024-08-21T16:15:35Z".parse().ok(),
                 weekly_movement: 62.5,
                 buy_max: 11_000_000.0,
                 buy_listed: 18,
                 sell_min: 15_000_000.0,
                 sell_listed: 95,
-            }),
+            })),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
         };
         println!(
             "Data abroad avg daily: \n {:?}",
-            mock_item.get_abroad_avg_daily().format_for_display()
+            mock_item.get_abroad_avg_daily(destination).format_for_display()
         );
         println!(
-            "Jita_buy price with tax: \n {:?}",
-            mock_item.get_jita_buy_price_with_tax().format_for_display()
+            "Source buy price with tax: \n {:?}",
+            mock_item.get_source_buy_price_with_tax(source).format_for_display()
         );
         println!(
             "Shipping price: \n {:?}",
-            mock_item.get_shipping_price().format_for_display()
+            mock_item
+                .get_shipping_price(TradeMode::BuyOrder, source, destination)
+                .format_for_display()
         );
         println!(
             "Abroad sell taxed: \n {:?}",
-            mock_item.get_abroad_sell_taxed().format_for_display()
+            mock_item.get_abroad_sell_taxed(destination).format_for_display()
         );
         println!(
-            "Jita_buy profit per unit: \n {:?}",
+            "Buy order profit per unit: \n {:?}",
             mock_item
-                .get_profit_jita_buy_per_unit()
+                .get_profit_per_unit(TradeMode::BuyOrder, source, destination)
                 .format_for_display()
         );
         println!(
-            "Jita_buy dialy profit: \n {:?}",
-            mock_item.get_profit_jita_buy_daily().format_for_display()
+            "Buy order daily profit: \n {:?}",
+            mock_item
+                .get_profit_daily(
+                    TradeMode::BuyOrder,
+                    DailyVolumeEstimate::Heuristic,
+                    source,
+                    destination,
+                )
+                .format_for_display()
         );
         println!(
             "Money freeze rate buy: \n {:?}",
-            mock_item.get_money_freeze_buy().format_for_display()
+            mock_item
+                .get_money_freeze(
+                    TradeMode::BuyOrder,
+                    DailyVolumeEstimate::Heuristic,
+                    source,
+                    destination,
+                )
+                .format_for_display()
         );
         println!(
             "Margin: \n {:?}",
             mock_item
-                .get_margin_jita_buy()
+                .get_margin(TradeMode::BuyOrder, source, destination)
                 .format_for_display_percentage()
         );
-        println!("Freeze rate: \n {:?}", mock_item.get_freeze_rate());
+        println!(
+            "Freeze rate: \n {:?}",
+            mock_item.get_freeze_rate(
+                TradeMode::BuyOrder,
+                DailyVolumeEstimate::Heuristic,
+                source,
+                destination,
+            )
+        );
+    }
+
+    #[test]
+    fn daily_volume_estimate_prefers_history_when_asked() {
+        let trade_data = TradeData {
+            updated: "2024-08-21T16:15:35Z".parse().ok(),
+            weekly_movement: 70.0,
+            buy_max: 11_000_000.0,
+            buy_listed: 18,
+            sell_min: 15_000_000.0,
+            sell_listed: 70,
+        };
+        let mut item = ItemData {
+            type_id: 11192,
+            type_volume: 2500.0,
+            type_name: "Buzzard".to_owned(),
+            markets: markets(Some(trade_data.clone()), Some(trade_data)),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::default();
+        let destination = &config.destinations()[0];
+        assert_eq!(item.get_abroad_avg_daily(destination), Some(10.0));
+        assert_eq!(
+            item.get_abroad_avg_daily_estimate(DailyVolumeEstimate::History, destination),
+            Some(10.0)
+        );
+
+        let stats = [(
+            11192,
+            history::history::HistoryStats {
+                days: 20,
+                avg_daily_volume: 4.0,
+                median_price: 14_000_000.0,
+                volatility: 0.05,
+            },
+        )];
+        history::history::attach_history_stats(
+            std::slice::from_mut(&mut item),
+            destination.location_id,
+            &stats.into_iter().collect(),
+        );
+        let max_quote_age = chrono::TimeDelta::MAX;
+        let heuristic = ExtendedItemData::new(
+            item.clone(),
+            DailyVolumeEstimate::Heuristic,
+            max_quote_age,
+            &config,
+        );
+        let history =
+            ExtendedItemData::new(item, DailyVolumeEstimate::History, max_quote_age, &config);
+
+        assert_eq!(heuristic.abroad_avg_daily, Some(10.0));
+        assert_eq!(history.abroad_avg_daily, Some(4.0));
+        assert_eq!(history.abroad_median_price, Some(14_000_000.0));
+        assert_eq!(
+            history.profit_buy_order_daily,
+            history.profit_buy_order_per_unit.map(|profit| 4.0 * profit)
+        );
+    }
+
+    #[test]
+    fn item_missing_abroad_is_not_seeded_opportunity() {
+        let jita = TradeData {
+            updated: "2024-08-21T16:16:48Z".parse().ok(),
+            weekly_movement: 865.2,
+            buy_max: 10_000_000.0,
+            buy_listed: 138,
+            sell_min: 23_200_000.0,
+            sell_listed: 758,
+        };
+        let item = ItemData {
+            type_id: 11192,
+            type_volume: 2500.0,
+            type_name: "Buzzard".to_owned(),
+            markets: markets(Some(jita), None),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::default();
+        let extended = ExtendedItemData::new(
+            item,
+            DailyVolumeEstimate::Heuristic,
+            chrono::TimeDelta::MAX,
+            &config,
+        );
+
+        assert!(extended.not_seeded_abroad);
+        assert_eq!(extended.source_buy_with_tax, Some(10_000_000.0 * 0.015 + 10_000_000.0));
+        assert_eq!(extended.abroad_sell_taxed, None);
+        assert_eq!(extended.profit_buy_order_per_unit, None);
+        assert_eq!(extended.freeze_rate, None);
+    }
+
+    #[test]
+    fn instant_modes_trade_fees_for_speed() {
+        let item = ItemData {
+            type_id: 11192,
+            type_volume: 0.0,
+            type_name: "Buzzard".to_owned(),
+            markets: markets(Some(TradeData {
+                updated: "2024-08-21T16:16:48Z".parse().ok(),
+                weekly_movement: 865.2,
+                buy_max: 10_000_000.0,
+                buy_listed: 138,
+                sell_min: 11_000_000.0,
+                sell_listed: 758,
+            }), Some(TradeData {
+                updated: "2024-08-21T16:15:35Z".parse().ok(),
+                weekly_movement: 70.0,
+                buy_max: 13_000_000.0,
+                buy_listed: 18,
+                sell_min: 15_000_000.0,
+                sell_listed: 70,
+            })),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::default();
+        let sales_tax = config.fees.sales_tax();
+        let extended = ExtendedItemData::new(
+            item,
+            DailyVolumeEstimate::Heuristic,
+            chrono::TimeDelta::MAX,
+            &config,
+        );
+
+        let assert_close = |actual: Option<f64>, expected: f64| {
+            assert!((actual.unwrap() - expected).abs() < 1e-3, "{:?} != {}", actual, expected)
+        };
+        let sell_taxed = 15_000_000.0 * (1.0 - 0.01 - sales_tax);
+        let buy_with_tax = 10_000_000.0 * 1.015;
+        assert_close(extended.abroad_buy_taxed, 13_000_000.0 * (1.0 - sales_tax));
+        assert_close(extended.profit_instant_buy_per_unit, sell_taxed - 11_000_000.0);
+        assert_close(
+            extended.profit_instant_sell_per_unit,
+            13_000_000.0 * (1.0 - sales_tax) - buy_with_tax,
+        );
+        assert_close(extended.money_freeze_instant_buy, 10.0 * 11_000_000.0);
+        assert_close(extended.money_freeze_instant_sell, 10.0 * buy_with_tax);
+        assert_close(
+            extended.profit_instant_sell_daily,
+            10.0 * extended.profit_instant_sell_per_unit.unwrap(),
+        );
+        assert!(extended.margin_buy_order > extended.margin_instant_buy);
+        assert!(extended.margin_buy_order > extended.margin_instant_sell);
+    }
+
+    #[test]
+    fn row_shows_the_most_profitable_destination() {
+        let quote = |buy_max: f64, sell_min: f64| TradeData {
+            updated: "2024-08-21T16:15:35Z".parse().ok(),
+            weekly_movement: 70.0,
+            buy_max,
+            buy_listed: 18,
+            sell_min,
+            sell_listed: 70,
+        };
+        let market = |buy_max: f64, sell_min: f64| datagetter::datagetter::MarketData {
+            trade_data: Some(quote(buy_max, sell_min)),
+            history: None,
+        };
+        let item = ItemData {
+            type_id: 11192,
+            type_volume: 2500.0,
+            type_name: "Buzzard".to_owned(),
+            markets: [
+                (60003760, market(10_000_000.0, 11_000_000.0)),
+                (1, market(1.0, 13_000_000.0)),
+                (2, market(1.0, 15_000_000.0)),
+            ]
+            .into(),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::parse(
+            "[[destinations]]
+            name = \"near\"
+            location_id = 1
+
+            [[destinations]]
+            name = \"far\"
+            location_id = 2
+
+            [[destinations]]
+            name = \"unseeded\"
+            location_id = 3",
+        )
+        .unwrap();
+
+        let row = ExtendedItemData::new(
+            item,
+            DailyVolumeEstimate::Heuristic,
+            chrono::TimeDelta::MAX,
+            &config,
+        );
+
+        assert_eq!(row.destination, "far");
+        assert_eq!(row.abroad_trade_data, Some(quote(1.0, 15_000_000.0)));
+        let names: Vec<&str> = row.destinations.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["near", "far", "unseeded"]);
+        assert_eq!(row.destinations[1].profit_daily, row.profit_buy_order_daily);
+        assert!(row.destinations[0].profit_daily < row.profit_buy_order_daily);
+        assert!(row.destinations[2].not_seeded);
+        assert_eq!(row.destinations[2].profit_daily, None);
+    }
+
+    #[test]
+    fn cheapest_landed_cost_picks_the_source() {
+        let quote = |buy_max: f64| TradeData {
+            updated: "2024-08-21T16:15:35Z".parse().ok(),
+            weekly_movement: 70.0,
+            buy_max,
+            buy_listed: 18,
+            sell_min: 20_000_000.0,
+            sell_listed: 70,
+        };
+        let market = |buy_max: f64| datagetter::datagetter::MarketData {
+            trade_data: Some(quote(buy_max)),
+            history: None,
+        };
+        let item = ItemData {
+            type_id: 11192,
+            type_volume: 1000.0,
+            type_name: "Buzzard".to_owned(),
+            markets: [
+                (60003760, market(10_000_000.0)),
+                (60008494, market(9_500_000.0)),
+                (1, market(11_000_000.0)),
+                (2, market(10_200_000.0)),
+            ]
+            .into(),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::parse(
+            "[fees]
+            structure_broker_fees = {}
+
+            [freight]
+            price_per_m3 = 1000.0
+
+            [[sources]]
+            name = \"jita\"
+            location_id = 60003760
+
+            [[sources]]
+            name = \"amarr\"
+            location_id = 60008494
+            freight.near = { price_per_m3 = 2000.0 }
+
+            [[sources]]
+            name = \"local\"
+            location_id = 2
+
+            [[destinations]]
+            name = \"near\"
+            location_id = 1
+
+            [[destinations]]
+            name = \"far\"
+            location_id = 2",
+        )
+        .unwrap();
+
+        let row = ExtendedItemData::new(
+            item,
+            DailyVolumeEstimate::Heuristic,
+            chrono::TimeDelta::MAX,
+            &config,
+        );
+
+        // Amarr is cheaper than Jita but not after its freight to "near".
+        assert_eq!(row.destinations[0].source, "jita");
+        // Buying at "far" itself saves the freight the cheaper Amarr order costs.
+        assert_eq!(row.destinations[1].source, "local");
+        let fee = config.fees.npc_broker_fee(&config.fees.jita_standings);
+        assert_eq!(
+            row.destinations[1].landed_cost,
+            Some(10_200_000.0 * fee + 10_200_000.0)
+        );
+        assert_eq!(row.source, "local");
+        assert_eq!(row.source_trade_data, Some(quote(10_200_000.0)));
+    }
+
+    #[test]
+    fn reversed_config_prices_exports_to_jita() {
+        let quote = |buy_max: f64, sell_min: f64| TradeData {
+            updated: "2024-08-21T16:15:35Z".parse().ok(),
+            weekly_movement: 700.0,
+            buy_max,
+            buy_listed: 50,
+            sell_min,
+            sell_listed: 300,
+        };
+        let item = ItemData {
+            type_id: 25595,
+            type_volume: 10.0,
+            type_name: "Alloyed Tritanium Bar".to_owned(),
+            markets: markets(
+                Some(quote(28_000.0, 30_000.0)),
+                Some(quote(10_000.0, 12_000.0)),
+            ),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::parse(
+            "[reverse.freight]
+            price_per_m3 = 100.0",
+        )
+        .unwrap();
+
+        let row = ExtendedItemData::new(
+            item,
+            DailyVolumeEstimate::Heuristic,
+            chrono::TimeDelta::MAX,
+            &config.reversed(),
+        );
+
+        assert_eq!(row.source, "abroad");
+        assert_eq!(row.destination, "jita");
+        assert_eq!(row.source_trade_data, Some(quote(10_000.0, 12_000.0)));
+        assert_eq!(row.abroad_trade_data, Some(quote(28_000.0, 30_000.0)));
+        // The keepstar buy order pays the structure fee, hauling pays the export freight.
+        assert_eq!(row.landed_cost, Some(10_000.0 * 0.01 + 10_000.0 + 10.0 * 100.0));
+        assert!(row.profit_buy_order_per_unit.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn esi_priced_markets_get_their_volume_from_history() {
+        use crate::esi::esi::{orders_to_trade_data, MarketOrder};
+        use crate::filters::filters::{OpportunityFilter, OpportunityRule};
+
+        let config = Config::default();
+        let (jita, keepstar) = (config.stations.jita_station_id, 1030049082711);
+        let order = |order_id: i64, location_id: i64, is_buy_order: bool, price: f64| {
+            MarketOrder {
+                order_id,
+                type_id: 11192,
+                location_id,
+                is_buy_order,
+                price,
+                volume_remain: 50,
+                volume_total: 50,
+                issued: "2024-08-21T16:15:35Z".to_owned(),
+            }
+        };
+        let orders = [
+            order(1, jita, true, 1_000_000.0),
+            order(2, jita, false, 1_100_000.0),
+            order(3, keepstar, false, 2_000_000.0),
+        ];
+        let updated = "2024-08-21T16:15:35Z".parse().unwrap();
+        let mut item = ItemData {
+            type_id: 11192,
+            type_volume: 10.0,
+            type_name: "Buzzard".to_owned(),
+            markets: markets(
+                orders_to_trade_data(&orders, jita, &[11192], updated).remove(&11192),
+                orders_to_trade_data(&orders, keepstar, &[11192], updated).remove(&11192),
+            ),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let stats = [(
+            11192,
+            history::history::HistoryStats {
+                days: 30,
+                avg_daily_volume: 50.0,
+                median_price: 2_000_000.0,
+                volatility: 0.05,
+            },
+        )];
+        history::history::attach_history_stats(
+            std::slice::from_mut(&mut item),
+            keepstar,
+            &stats.into_iter().collect(),
+        );
+        let filter = OpportunityFilter {
+            enabled: true,
+            ..OpportunityFilter::default()
+        };
+        let max_quote_age = chrono::TimeDelta::MAX;
+
+        // The order book alone says nothing about how much sells.
+        let heuristic = ExtendedItemData::new(
+            item.clone(),
+            DailyVolumeEstimate::Heuristic,
+            max_quote_age,
+            &config,
+        );
+        assert_eq!(heuristic.abroad_avg_daily, None);
+        assert!(filter
+            .failed_rules(&heuristic)
+            .contains(&OpportunityRule::DailyVolume));
+
+        let history =
+            ExtendedItemData::new(item, DailyVolumeEstimate::History, max_quote_age, &config);
+        assert_eq!(history.abroad_avg_daily, Some(50.0));
+        assert_eq!(history.abroad_stocked_ratio, Some(50.0 / 350.0));
+        assert_eq!(filter.failed_rules(&history), vec![]);
+    }
+
+    #[test]
+    fn source_without_buy_orders_is_never_cheapest() {
+        let quote = |buy_max: f64, buy_listed: i64| TradeData {
+            updated: "2024-08-21T16:15:35Z".parse().ok(),
+            weekly_movement: 70.0,
+            buy_max,
+            buy_listed,
+            sell_min: 20_000_000.0,
+            sell_listed: 70,
+        };
+        let market = |trade_data| datagetter::datagetter::MarketData {
+            trade_data: Some(trade_data),
+            history: None,
+        };
+        let item = ItemData {
+            type_id: 11192,
+            type_volume: 1000.0,
+            type_name: "Buzzard".to_owned(),
+            markets: [
+                (60003760, market(quote(10_000_000.0, 18))),
+                (60008494, market(quote(0.0, 0))),
+                (1, market(quote(11_000_000.0, 18))),
+            ]
+            .into(),
+            market_group_id: None,
+            metadata: ItemMetadata::default(),
+        };
+        let config = Config::parse(
+            "[[sources]]
+            name = \"amarr\"
+            location_id = 60008494
+
+            [[sources]]
+            name = \"jita\"
+            location_id = 60003760
+
+            [[destinations]]
+            name = \"keepstar\"
+            location_id = 1",
+        )
+        .unwrap();
+        let sources = config.sources();
+        let destination = &config.destinations()[0];
+
+        assert_eq!(item.get_landed_cost(&sources[0], destination), None);
+        assert_eq!(cheapest_source(&item, &sources, destination).name, "jita");
     }
 }
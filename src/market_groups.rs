@@ -0,0 +1,252 @@
+pub mod market_groups {
+    use crate::datagetter::datagetter::Result;
+    use crate::sde::sde::{missing_tables, Sde};
+    use std::collections::{BTreeMap, HashSet};
+
+    pub const MARKET_GROUPS_TABLE: &str = "invMarketGroups";
+    pub const PATH_SEPARATOR: &str = ">";
+
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct MarketGroup {
+        pub id: i32,
+        pub parent_id: Option<i32>,
+        pub name: String,
+    }
+
+    /// The SDE market browser tree, e.g. "Ships > Frigates > Standard Frigates".
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct MarketGroupTree {
+        groups: BTreeMap<i32, MarketGroup>,
+        /// Children per parent, `None` holding the roots. Sorted by name.
+        children: BTreeMap<Option<i32>, Vec<i32>>,
+    }
+
+    impl MarketGroupTree {
+        pub fn load(sde: &Sde) -> Result<Self> {
+            if !missing_tables(sde.conn(), &[MARKET_GROUPS_TABLE])?.is_empty() {
+                return Err(format!(
+                    "{} has no {} table, market group filters need it",
+                    sde.describe(),
+                    MARKET_GROUPS_TABLE
+                )
+                .into());
+            }
+            let mut stmt = sde.conn().prepare(
+                "SELECT marketGroupID, parentGroupID, marketGroupName FROM invMarketGroups",
+            )?;
+            let groups = stmt
+                .query_map([], |row| {
+                    Ok(MarketGroup {
+                        id: row.get(0)?,
+                        parent_id: row.get(1)?,
+                        name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(MarketGroupTree::from_groups(groups))
+        }
+
+        pub fn from_groups(groups: Vec<MarketGroup>) -> Self {
+            let groups: BTreeMap<i32, MarketGroup> =
+                groups.into_iter().map(|group| (group.id, group)).collect();
+            let mut children: BTreeMap<Option<i32>, Vec<i32>> = BTreeMap::new();
+            for group in groups.values() {
+                // A parent missing from the dump would hide the group, treat it as a root.
+                let parent_id = group.parent_id.filter(|id| groups.contains_key(id));
+                children.entry(parent_id).or_default().push(group.id);
+            }
+            for ids in children.values_mut() {
+                ids.sort_by(|a, b| groups[a].name.cmp(&groups[b].name));
+            }
+            MarketGroupTree { groups, children }
+        }
+
+        pub fn get(&self, id: i32) -> Option<&MarketGroup> {
+            self.groups.get(&id)
+        }
+
+        pub fn roots(&self) -> &[i32] {
+            self.children.get(&None).map(Vec::as_slice).unwrap_or(&[])
+        }
+
+        pub fn children(&self, id: i32) -> &[i32] {
+            self.children
+                .get(&Some(id))
+                .map(Vec::as_slice)
+                .unwrap_or(&[])
+        }
+
+        /// The group followed by its parents up to the root.
+        pub fn ancestors(&self, id: i32) -> Vec<i32> {
+            let mut result = vec![];
+            let mut current = self.groups.get(&id);
+            while let Some(group) = current {
+                // Guards against a parent cycle in a hand-edited dump.
+                if result.contains(&group.id) {
+                    break;
+                }
+                result.push(group.id);
+                current = group
+                    .parent_id
+                    .and_then(|parent_id| self.groups.get(&parent_id));
+            }
+            result
+        }
+
+        /// "Ships > Frigates" for the Frigates group.
+        pub fn path_of(&self, id: i32) -> String {
+            let names: Vec<&str> = self
+                .ancestors(id)
+                .iter()
+                .rev()
+                .map(|id| self.groups[id].name.as_str())
+                .collect();
+            names.join(&format!(" {} ", PATH_SEPARATOR))
+        }
+
+        /// Walks a "Ships > Frigates" path from the roots, names compared case-insensitively.
+        pub fn find_by_path(&self, path: &str) -> Result<i32> {
+            let mut candidates = self.roots();
+            let mut found = None;
+            for name in path.split(PATH_SEPARATOR).map(str::trim) {
+                let group_id = candidates
+                    .iter()
+                    .find(|id| self.groups[id].name.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| {
+                        let known: Vec<&str> = candidates
+                            .iter()
+                            .map(|id| self.groups[id].name.as_str())
+                            .collect();
+                        format!(
+                            "unknown market group {:?} in {:?}, expected one of: {}",
+                            name,
+                            path,
+                            known.join(", ")
+                        )
+                    })?;
+                found = Some(*group_id);
+                candidates = self.children(*group_id);
+            }
+            found.ok_or_else(|| format!("empty market group path {:?}", path).into())
+        }
+
+        /// The group and everything below it.
+        pub fn descendants(&self, id: i32) -> HashSet<i32> {
+            let mut result = HashSet::new();
+            let mut pending = vec![id];
+            while let Some(current) = pending.pop() {
+                if result.insert(current) {
+                    pending.extend_from_slice(self.children(current));
+                }
+            }
+            result
+        }
+
+        pub fn descendants_of_all(&self, ids: &[i32]) -> HashSet<i32> {
+            ids.iter().flat_map(|id| self.descendants(*id)).collect()
+        }
+    }
+
+    /// Tradable type IDs in the given market groups and all their subgroups.
+    pub fn get_type_ids_in_market_groups(
+        sde: &Sde,
+        tree: &MarketGroupTree,
+        group_ids: &[i32],
+    ) -> Result<Vec<i32>> {
+        let mut market_group_ids: Vec<i32> =
+            tree.descendants_of_all(group_ids).into_iter().collect();
+        market_group_ids.sort();
+        let mut stmt = sde.conn().prepare(
+            "SELECT typeID FROM invTypes
+            WHERE marketGroupID IN (SELECT value FROM json_each(?1)) AND description <> ''
+            ORDER BY typeID",
+        )?;
+        let type_ids = stmt
+            .query_map([serde_json::to_string(&market_group_ids)?], |row| {
+                row.get(0)
+            })?
+            .collect::<rusqlite::Result<Vec<i32>>>()?;
+        if type_ids.is_empty() {
+            let paths: Vec<String> = group_ids.iter().map(|id| tree.path_of(*id)).collect();
+            return Err(format!("no tradable items in {}", paths.join(", ")).into());
+        }
+        Ok(type_ids)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::market_groups::market_groups::*;
+    use crate::sde::sde::Sde;
+    use crate::sde::tests::temp_sde;
+    use rusqlite::Connection as SQL_Connection;
+
+    pub fn group(id: i32, parent_id: Option<i32>, name: &str) -> MarketGroup {
+        MarketGroup {
+            id,
+            parent_id,
+            name: name.to_owned(),
+        }
+    }
+
+    pub fn sample_tree() -> MarketGroupTree {
+        MarketGroupTree::from_groups(vec![
+            group(4, None, "Ships"),
+            group(1361, Some(4), "Frigates"),
+            group(64, Some(1361), "Standard Frigates"),
+            group(391, Some(4), "Cruisers"),
+            group(9, None, "Ship Equipment"),
+            group(1, Some(9), "Hardeners"),
+        ])
+    }
+
+    #[test]
+    fn paths_resolve_both_ways() {
+        let tree = sample_tree();
+
+        assert_eq!(tree.find_by_path("Ships > Frigates").unwrap(), 1361);
+        assert_eq!(tree.find_by_path("ship equipment>hardeners").unwrap(), 1);
+        assert_eq!(tree.path_of(64), "Ships > Frigates > Standard Frigates");
+
+        let error = tree.find_by_path("Ships > Shuttles").err().unwrap();
+        assert!(error.to_string().contains("Cruisers, Frigates"));
+    }
+
+    #[test]
+    fn descendants_include_the_whole_subtree() {
+        let tree = sample_tree();
+
+        let mut ids: Vec<i32> = tree.descendants(4).into_iter().collect();
+        ids.sort();
+        assert_eq!(ids, vec![4, 64, 391, 1361]);
+        assert_eq!(tree.ancestors(64), vec![64, 1361, 4]);
+        assert_eq!(tree.roots(), &[9, 4]);
+    }
+
+    #[test]
+    fn market_groups_resolve_to_type_ids() {
+        let path = temp_sde(
+            "market-groups",
+            &["invTypes", "invVolumes", MARKET_GROUPS_TABLE],
+        );
+        let conn = SQL_Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO invMarketGroups VALUES (4, NULL, 'Ships');
+            INSERT INTO invMarketGroups VALUES (1361, 4, 'Frigates');
+            INSERT INTO invMarketGroups VALUES (64, 1361, 'Standard Frigates');
+            INSERT INTO invMarketGroups VALUES (9, NULL, 'Ship Equipment');
+            INSERT INTO invTypes VALUES (603, 'Merlin', 2500, 64, 'Frigate');
+            INSERT INTO invTypes VALUES (11192, 'Buzzard', 2500, 1361, 'Covert ops');
+            INSERT INTO invTypes VALUES (2281, 'Adaptive Invulnerability Field II', 5, 9, 'Shield');",
+        )
+        .unwrap();
+        let sde = Sde::open(&path).unwrap();
+        let tree = MarketGroupTree::load(&sde).unwrap();
+
+        let frigates = tree.find_by_path("Ships > Frigates").unwrap();
+        assert_eq!(
+            get_type_ids_in_market_groups(&sde, &tree, &[frigates]).unwrap(),
+            vec![603, 11192]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+pub mod provider {
+    use crate::datagetter::datagetter::{
+        get_item_data_from_api, trade_data_by_type, FailedBatch, FetchReport, Result,
+        TradeDataByType, GOONMETRICS_BASE_URL,
+    };
+    use crate::http::http::HttpClient;
+    use async_trait::async_trait;
+    use std::time::Instant;
+
+    /// Source of market prices for a single market (station, structure or region).
+    /// Every provider returns the same normalized `TradeData` keyed by type ID,
+    /// so the merge and the metrics don't care where the prices come from.
+    #[async_trait]
+    pub trait MarketDataProvider: Send + Sync {
+        fn name(&self) -> String;
+        async fn get_trade_data(&self, type_ids: &[i32]) -> Result<TradeDataByType>;
+
+        /// Like `get_trade_data`, but keeps whatever succeeded. Providers that cannot
+        /// fail partially report the whole request as a single failed batch.
+        async fn get_trade_data_report(&self, type_ids: &[i32]) -> FetchReport<TradeDataByType> {
+            let started = Instant::now();
+            let (data, failed_batches) = match self.get_trade_data(type_ids).await {
+                Ok(data) => (data, vec![]),
+                Err(e) => (
+                    TradeDataByType::new(),
+                    vec![FailedBatch {
+                        type_ids: type_ids.to_vec(),
+                        cause: e.to_string(),
+                    }],
+                ),
+            };
+            FetchReport {
+                data,
+                failed_batches,
+                elapsed: started.elapsed(),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct GoonmetricsProvider {
+        pub base_url: String,
+        pub station_id: String,
+        pub max_concurrent_requests: usize,
+        http: HttpClient,
+    }
+
+    impl GoonmetricsProvider {
+        pub fn new(station_id: &str, http: HttpClient, max_concurrent_requests: usize) -> Self {
+            GoonmetricsProvider::with_base_url(
+                GOONMETRICS_BASE_URL,
+                station_id,
+                http,
+                max_concurrent_requests,
+            )
+        }
+
+        pub fn with_base_url(
+            base_url: &str,
+            station_id: &str,
+            http: HttpClient,
+            max_concurrent_requests: usize,
+        ) -> Self {
+            GoonmetricsProvider {
+                base_url: base_url.trim_end_matches('/').to_owned(),
+                station_id: station_id.to_owned(),
+                max_concurrent_requests,
+                http,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl MarketDataProvider for GoonmetricsProvider {
+        fn name(&self) -> String {
+            format!("goonmetrics:{}", self.station_id)
+        }
+
+        async fn get_trade_data(&self, type_ids: &[i32]) -> Result<TradeDataByType> {
+            self.get_trade_data_report(type_ids).await.into_result()
+        }
+
+        async fn get_trade_data_report(&self, type_ids: &[i32]) -> FetchReport<TradeDataByType> {
+            let report = get_item_data_from_api(
+                &self.http,
+                &self.base_url,
+                &self.station_id,
+                &type_ids.to_vec(),
+                self.max_concurrent_requests,
+            )
+            .await;
+            FetchReport {
+                data: trade_data_by_type(&report.data),
+                failed_batches: report.failed_batches,
+                elapsed: report.elapsed,
+            }
+        }
+    }
+}
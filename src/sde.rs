@@ -0,0 +1,505 @@
+pub mod sde {
+    use crate::datagetter::datagetter::{get_app_data_dir, Result};
+    use bzip2::read::BzDecoder;
+    use chrono::{DateTime, Utc};
+    use rusqlite::{params, Connection as SQL_Connection, OpenFlags, OptionalExtension};
+    use std::fmt;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub const SDE_PATH_ENV: &str = "GESCHEFTMACHER_SDE_PATH";
+    pub const SDE_FILE_NAME: &str = "eve.db";
+    pub const SDE_DOWNLOAD_URL: &str = "https://www.fuzzwork.co.uk/dump/latest/";
+    /// Tables every query against the SDE relies on.
+    pub const REQUIRED_TABLES: [&str; 2] = ["invTypes", "invVolumes"];
+    /// Optional key/value table recording which SDE build a dump is.
+    pub const SDE_META_TABLE: &str = "sde_meta";
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum SdePathSource {
+        Flag,
+        Env,
+        Config,
+        DataDir,
+    }
+
+    impl fmt::Display for SdePathSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                SdePathSource::Flag => write!(f, "--sde-path"),
+                SdePathSource::Env => write!(f, "{}", SDE_PATH_ENV),
+                SdePathSource::Config => write!(f, "config file"),
+                SdePathSource::DataDir => write!(f, "data dir"),
+            }
+        }
+    }
+
+    pub fn default_sde_path() -> PathBuf {
+        get_app_data_dir().join(SDE_FILE_NAME)
+    }
+
+    /// First of: the `--sde-path` flag, `GESCHEFTMACHER_SDE_PATH`, the config file, the data dir.
+    pub fn resolve_sde_path(
+        flag: Option<PathBuf>,
+        config: Option<PathBuf>,
+    ) -> (PathBuf, SdePathSource) {
+        let env = std::env::var_os(SDE_PATH_ENV)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from);
+        resolve_sde_path_from(flag, env, config)
+    }
+
+    pub fn resolve_sde_path_from(
+        flag: Option<PathBuf>,
+        env: Option<PathBuf>,
+        config: Option<PathBuf>,
+    ) -> (PathBuf, SdePathSource) {
+        if let Some(path) = flag {
+            return (path, SdePathSource::Flag);
+        }
+        if let Some(path) = env {
+            return (path, SdePathSource::Env);
+        }
+        if let Some(path) = config {
+            return (path, SdePathSource::Config);
+        }
+        (default_sde_path(), SdePathSource::DataDir)
+    }
+
+    /// The static data export, opened read-only and checked for the tables we query.
+    pub struct Sde {
+        conn: SQL_Connection,
+        pub path: PathBuf,
+        pub version: String,
+    }
+
+    impl Sde {
+        pub fn open_resolved(flag: Option<PathBuf>, config: Option<PathBuf>) -> Result<Self> {
+            let (path, source) = resolve_sde_path(flag, config);
+            Sde::open(&path).map_err(|e| format!("{} (SDE path from {})", e, source).into())
+        }
+
+        pub fn open(path: &Path) -> Result<Self> {
+            if !path.is_file() {
+                return Err(format!(
+                    "SDE database not found at {:?}. Download the sqlite dump from {} \
+                    and run `import-sde`, pass --sde-path or set {}",
+                    path, SDE_DOWNLOAD_URL, SDE_PATH_ENV
+                )
+                .into());
+            }
+            let conn = SQL_Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .map_err(|e| format!("cannot open SDE {:?}: {}", path, e))?;
+
+            let missing = missing_tables(&conn, &REQUIRED_TABLES)?;
+            if !missing.is_empty() {
+                return Err(format!(
+                    "{:?} is not a usable SDE, missing tables: {}",
+                    path,
+                    missing.join(", ")
+                )
+                .into());
+            }
+            let version = read_version(&conn, path)?;
+            Ok(Sde {
+                conn,
+                path: path.to_owned(),
+                version,
+            })
+        }
+
+        pub fn conn(&self) -> &SQL_Connection {
+            &self.conn
+        }
+
+        pub fn describe(&self) -> String {
+            format!("{} ({:?})", self.version, self.path)
+        }
+    }
+
+    pub fn missing_tables(conn: &SQL_Connection, tables: &[&str]) -> Result<Vec<String>> {
+        let mut stmt = conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?1")?;
+        let mut missing = vec![];
+        for table in tables {
+            if !stmt.exists([table])? {
+                missing.push(table.to_string());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The version recorded in the dump, otherwise the file date as the best guess
+    /// of which build this is.
+    fn read_version(conn: &SQL_Connection, path: &Path) -> Result<String> {
+        if missing_tables(conn, &[SDE_META_TABLE])?.is_empty() {
+            let version: Option<String> = conn
+                .query_row(
+                    &format!("SELECT value FROM {} WHERE key = 'version'", SDE_META_TABLE),
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(version) = version {
+                return Ok(format!("SDE {}", version));
+            }
+        }
+        let modified: DateTime<Utc> = fs::metadata(path)?.modified()?.into();
+        Ok(format!(
+            "SDE of unknown build, file dated {}",
+            modified.format("%Y-%m-%d")
+        ))
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct SdeImport {
+        pub installed: PathBuf,
+        /// Where the replaced SDE was kept, `None` on a first install.
+        pub previous: Option<PathBuf>,
+        pub version: String,
+    }
+
+    pub fn previous_sde_path(target: &Path) -> PathBuf {
+        sibling_path(target, "previous")
+    }
+
+    fn sibling_path(target: &Path, suffix: &str) -> PathBuf {
+        let mut name = target.file_name().unwrap_or_default().to_owned();
+        name.push(".");
+        name.push(suffix);
+        target.with_file_name(name)
+    }
+
+    /// Installs a Fuzzwork dump at `target`: `sqlite-latest.sqlite.bz2`, an already
+    /// decompressed sqlite file, or the CSV dump directory. The dump is assembled
+    /// next to `target`, verified, and only then renamed over it, so a failed import
+    /// leaves the current SDE untouched. The replaced SDE is kept for `rollback_sde`.
+    pub fn import_sde(source: &Path, target: &Path, version: Option<String>) -> Result<SdeImport> {
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let staging = sibling_path(target, "importing");
+        let _ = fs::remove_file(&staging);
+
+        let staged = stage_sde(source, &staging, version);
+        let version = match staged {
+            Ok(version) => version,
+            Err(e) => {
+                let _ = fs::remove_file(&staging);
+                return Err(format!("cannot import SDE from {:?}: {}", source, e).into());
+            }
+        };
+
+        let previous = if target.exists() {
+            let previous = previous_sde_path(target);
+            let _ = fs::remove_file(&previous);
+            // A hard link keeps the current SDE in place until the rename below.
+            if fs::hard_link(target, &previous).is_err() {
+                fs::copy(target, &previous)?;
+            }
+            Some(previous)
+        } else {
+            None
+        };
+        fs::rename(&staging, target)?;
+
+        Ok(SdeImport {
+            installed: target.to_owned(),
+            previous,
+            version,
+        })
+    }
+
+    /// Swaps the SDE at `target` with the one the last import replaced.
+    pub fn rollback_sde(target: &Path) -> Result<PathBuf> {
+        let previous = previous_sde_path(target);
+        if !previous.is_file() {
+            return Err(format!("no previous SDE at {:?} to roll back to", previous).into());
+        }
+        let swap = sibling_path(target, "rollback");
+        if target.exists() {
+            fs::rename(target, &swap)?;
+        }
+        fs::rename(&previous, target)?;
+        if swap.exists() {
+            fs::rename(&swap, &previous)?;
+        }
+        Ok(previous)
+    }
+
+    fn stage_sde(source: &Path, staging: &Path, version: Option<String>) -> Result<String> {
+        if source.is_dir() {
+            import_csv_dir(source, staging)?;
+        } else if source.extension().is_some_and(|ext| ext == "bz2") {
+            let mut decoder = BzDecoder::new(fs::File::open(source)?);
+            let mut staged = fs::File::create(staging)?;
+            io::copy(&mut decoder, &mut staged)?;
+            staged.sync_all()?;
+        } else {
+            fs::copy(source, staging)?;
+        }
+
+        let conn = SQL_Connection::open(staging)?;
+        let missing = missing_tables(&conn, &REQUIRED_TABLES)?;
+        if !missing.is_empty() {
+            return Err(format!("dump lacks tables: {}", missing.join(", ")).into());
+        }
+        let version = version.unwrap_or_else(|| {
+            let name = source.file_name().unwrap_or_default().to_string_lossy();
+            format!("imported {} from {}", Utc::now().format("%Y-%m-%d"), name)
+        });
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value TEXT)",
+            SDE_META_TABLE
+        ))?;
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (key, value) VALUES ('version', ?1)",
+                SDE_META_TABLE
+            ),
+            params![version],
+        )?;
+        Ok(version)
+    }
+
+    /// One table per `<table>.csv`, columns from the header. NUMERIC affinity turns IDs
+    /// and volumes back into numbers; Fuzzwork writes NULL as `None`.
+    fn import_csv_dir(dir: &Path, staging: &Path) -> Result<()> {
+        let mut conn = SQL_Connection::open(staging)?;
+        let tx = conn.transaction()?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "csv") {
+                continue;
+            }
+            let table = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let mut reader = csv::Reader::from_path(&path)
+                .map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+            let headers: Vec<String> = reader
+                .headers()
+                .map_err(|e| format!("cannot read {:?}: {}", path, e))?
+                .iter()
+                .map(|header| format!("\"{}\"", header.replace('"', "\"\"")))
+                .collect();
+            tx.execute_batch(&format!(
+                "CREATE TABLE \"{}\" ({})",
+                table,
+                headers
+                    .iter()
+                    .map(|header| format!("{} NUMERIC", header))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))?;
+            let mut insert = tx.prepare(&format!(
+                "INSERT INTO \"{}\" ({}) VALUES ({})",
+                table,
+                headers.join(", "),
+                vec!["?"; headers.len()].join(", ")
+            ))?;
+            for record in reader.records() {
+                let record = record.map_err(|e| format!("bad row in {:?}: {}", path, e))?;
+                let values = record
+                    .iter()
+                    .map(|value| if value == "None" { None } else { Some(value) });
+                insert.execute(rusqlite::params_from_iter(values))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::sde::sde::*;
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+    use rusqlite::Connection as SQL_Connection;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// A minimal SDE with the tables and columns the queries use.
+    pub fn temp_sde(name: &str, tables: &[&str]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "gescheftmacher-sde-{}-{}.db",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let conn = SQL_Connection::open(&path).unwrap();
+        for table in tables {
+            let columns = match *table {
+                "invTypes" => {
+                    "typeID INTEGER PRIMARY KEY, typeName TEXT, volume REAL, \
+                    marketGroupID INTEGER, description TEXT"
+                }
+                "invVolumes" => "typeID INTEGER PRIMARY KEY, volume REAL",
+                "invGroups" => "groupID INTEGER PRIMARY KEY, categoryID INTEGER, groupName TEXT",
+                "invCategories" => "categoryID INTEGER PRIMARY KEY, categoryName TEXT",
+                "invMetaTypes" => "typeID INTEGER PRIMARY KEY, parentTypeID INTEGER, metaGroupID INTEGER",
+                "invMetaGroups" => "metaGroupID INTEGER PRIMARY KEY, metaGroupName TEXT",
+                "dgmTypeAttributes" => {
+                    "typeID INTEGER, attributeID INTEGER, valueInt INTEGER, valueFloat REAL"
+                }
+                "invMarketGroups" => {
+                    "marketGroupID INTEGER PRIMARY KEY, parentGroupID INTEGER, \
+                    marketGroupName TEXT"
+                }
+                SDE_META_TABLE => "key TEXT PRIMARY KEY, value TEXT",
+                _ => "id INTEGER",
+            };
+            conn.execute_batch(&format!("CREATE TABLE {} ({});", table, columns))
+                .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn path_resolution_order() {
+        let flag = Some(PathBuf::from("/flag/eve.db"));
+        let env = Some(PathBuf::from("/env/eve.db"));
+        let config = Some(PathBuf::from("/config/eve.db"));
+
+        assert_eq!(
+            resolve_sde_path_from(flag.clone(), env.clone(), config.clone()),
+            (PathBuf::from("/flag/eve.db"), SdePathSource::Flag)
+        );
+        assert_eq!(
+            resolve_sde_path_from(None, env, config.clone()),
+            (PathBuf::from("/env/eve.db"), SdePathSource::Env)
+        );
+        assert_eq!(
+            resolve_sde_path_from(None, None, config),
+            (PathBuf::from("/config/eve.db"), SdePathSource::Config)
+        );
+        assert_eq!(
+            resolve_sde_path_from(None, None, None),
+            (default_sde_path(), SdePathSource::DataDir)
+        );
+    }
+
+    #[test]
+    fn missing_file_is_a_clear_error() {
+        let error = Sde::open(&PathBuf::from("/nonexistent/eve.db"))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("SDE database not found"));
+    }
+
+    #[test]
+    fn missing_tables_are_reported() {
+        let path = temp_sde("no-volumes", &["invTypes"]);
+        let error = Sde::open(&path).err().unwrap();
+        assert!(error.to_string().contains("missing tables: invVolumes"));
+    }
+
+    #[test]
+    fn version_comes_from_meta_table() {
+        let path = temp_sde("versioned", &["invTypes", "invVolumes", SDE_META_TABLE]);
+        SQL_Connection::open(&path)
+            .unwrap()
+            .execute(
+                "INSERT INTO sde_meta (key, value) VALUES ('version', 'sde-20240501')",
+                [],
+            )
+            .unwrap();
+
+        let sde = Sde::open(&path).unwrap();
+        assert_eq!(sde.version, "SDE sde-20240501");
+
+        let unversioned = Sde::open(&temp_sde("unversioned", &["invTypes", "invVolumes"])).unwrap();
+        assert!(unversioned.version.starts_with("SDE of unknown build"));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "gescheftmacher-sde-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn compress(path: &PathBuf) -> PathBuf {
+        let archive = path.with_extension("sqlite.bz2");
+        let mut encoder = BzEncoder::new(
+            std::fs::File::create(&archive).unwrap(),
+            Compression::fast(),
+        );
+        encoder.write_all(&std::fs::read(path).unwrap()).unwrap();
+        encoder.finish().unwrap();
+        archive
+    }
+
+    #[test]
+    fn import_bz2_keeps_previous_for_rollback() {
+        let target = temp_dir("install").join("eve.db");
+        let first = compress(&temp_sde("first", &["invTypes", "invVolumes"]));
+        let second = compress(&temp_sde("second", &["invTypes", "invVolumes"]));
+
+        let import = import_sde(&first, &target, Some("first".to_owned())).unwrap();
+        assert_eq!(import.previous, None);
+        let import = import_sde(&second, &target, Some("second".to_owned())).unwrap();
+        assert_eq!(import.previous, Some(previous_sde_path(&target)));
+        assert_eq!(Sde::open(&target).unwrap().version, "SDE second");
+
+        rollback_sde(&target).unwrap();
+        assert_eq!(Sde::open(&target).unwrap().version, "SDE first");
+        assert_eq!(
+            Sde::open(&previous_sde_path(&target)).unwrap().version,
+            "SDE second"
+        );
+    }
+
+    #[test]
+    fn failed_import_leaves_current_sde() {
+        let target = temp_dir("failed").join("eve.db");
+        let good = compress(&temp_sde("good", &["invTypes", "invVolumes"]));
+        let broken = compress(&temp_sde("broken", &["invTypes"]));
+        import_sde(&good, &target, Some("good".to_owned())).unwrap();
+
+        let error = import_sde(&broken, &target, None).err().unwrap();
+
+        assert!(error.to_string().contains("invVolumes"));
+        assert_eq!(Sde::open(&target).unwrap().version, "SDE good");
+        assert!(!previous_sde_path(&target).exists());
+    }
+
+    #[test]
+    fn import_csv_dump_directory() {
+        let dump = temp_dir("csv");
+        std::fs::write(
+            dump.join("invTypes.csv"),
+            "typeID,typeName,volume,marketGroupID,description\n\
+            34,Tritanium,0.01,1857,The main building block\n\
+            22544,Hulk,3750,None,\"Exhumer, mining\"\n",
+        )
+        .unwrap();
+        std::fs::write(dump.join("invVolumes.csv"), "typeID,volume\n22544,3750\n").unwrap();
+        let target = temp_dir("csv-install").join("eve.db");
+
+        import_sde(&dump, &target, None).unwrap();
+
+        let sde = Sde::open(&target).unwrap();
+        assert!(sde.version.contains("imported"));
+        let (type_id, market_group): (i32, Option<i32>) = sde
+            .conn()
+            .query_row(
+                "SELECT typeID, marketGroupID FROM invTypes WHERE typeName = 'Hulk'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((type_id, market_group), (22544, None));
+    }
+}
@@ -0,0 +1,596 @@
+pub mod sso {
+    use crate::datagetter::datagetter::{get_app_data_dir, FetchOptions, Result};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use chrono::{DateTime, Duration, Utc};
+    use rand::RngCore;
+    use serde::{Deserialize, Serialize};
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    pub const SSO_AUTHORIZE_URL: &str = "https://login.eveonline.com/v2/oauth/authorize";
+    pub const SSO_TOKEN_URL: &str = "https://login.eveonline.com/v2/oauth/token";
+    pub const DEFAULT_CALLBACK_URL: &str = "http://localhost:8635/callback";
+    pub const STRUCTURE_MARKETS_SCOPE: &str = "esi-markets.structure_markets.v1";
+
+    /// Access tokens are refreshed this long before EVE SSO says they expire.
+    const EXPIRY_MARGIN_SECONDS: i64 = 60;
+    /// OAuth error for a spent or revoked code or refresh token.
+    const INVALID_GRANT: &str = "invalid_grant";
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct SsoConfig {
+        pub client_id: String,
+        pub callback_url: String,
+        pub authorize_url: String,
+        pub token_url: String,
+        pub scopes: Vec<String>,
+        /// Per token request, so a stalled login server cannot hang a fetch.
+        pub request_timeout: std::time::Duration,
+    }
+
+    impl SsoConfig {
+        pub fn new(client_id: &str) -> Self {
+            SsoConfig {
+                client_id: client_id.to_owned(),
+                callback_url: DEFAULT_CALLBACK_URL.to_owned(),
+                authorize_url: SSO_AUTHORIZE_URL.to_owned(),
+                token_url: SSO_TOKEN_URL.to_owned(),
+                scopes: vec![STRUCTURE_MARKETS_SCOPE.to_owned()],
+                request_timeout: FetchOptions::default().request_timeout,
+            }
+        }
+    }
+
+    /// PKCE pair, so the desktop app can log in without a client secret.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Pkce {
+        pub verifier: String,
+        pub challenge: String,
+    }
+
+    impl Pkce {
+        pub fn new() -> Self {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            Pkce::from_verifier(&URL_SAFE_NO_PAD.encode(bytes))
+        }
+
+        pub fn from_verifier(verifier: &str) -> Self {
+            let digest = Sha256::digest(verifier.as_bytes());
+            Pkce {
+                verifier: verifier.to_owned(),
+                challenge: URL_SAFE_NO_PAD.encode(digest),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    pub struct TokenResponse {
+        pub access_token: String,
+        pub expires_in: i64,
+        pub refresh_token: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    struct TokenErrorResponse {
+        error: String,
+    }
+
+    /// How EVE SSO answered a token request.
+    enum TokenReply {
+        Granted(String),
+        /// The code or refresh token is no longer accepted, only a new login helps.
+        InvalidGrant,
+    }
+
+    /// What survives between runs. Access tokens are short-lived and never written.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct StoredToken {
+        pub client_id: String,
+        pub refresh_token: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct TokenStore {
+        pub path: PathBuf,
+    }
+
+    impl TokenStore {
+        pub fn new(path: PathBuf) -> Self {
+            TokenStore { path }
+        }
+
+        pub fn default_location() -> Self {
+            TokenStore::new(get_app_data_dir().join("sso_token.json"))
+        }
+
+        pub fn load(&self) -> Result<Option<StoredToken>> {
+            if !self.path.exists() {
+                return Ok(None);
+            }
+            let body = fs::read_to_string(&self.path)?;
+            Ok(Some(serde_json::from_str(&body)?))
+        }
+
+        /// Writes through a temp file readable only by the current user and
+        /// renames it over the old one, so a crash never leaves half a token behind.
+        pub fn save(&self, token: &StoredToken) -> Result<()> {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let tmp_path = self.path.with_extension("json.tmp");
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut file = options.open(&tmp_path)?;
+            file.write_all(serde_json::to_string(token)?.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        }
+
+        pub fn clear(&self) -> Result<()> {
+            match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct AccessToken {
+        token: String,
+        expires_at: DateTime<Utc>,
+    }
+
+    pub struct SsoClient {
+        pub config: SsoConfig,
+        store: TokenStore,
+        http: reqwest::Client,
+        access_token: Mutex<Option<AccessToken>>,
+    }
+
+    impl SsoClient {
+        pub fn new(config: SsoConfig, store: TokenStore) -> Result<Self> {
+            let http = reqwest::Client::builder()
+                .timeout(config.request_timeout)
+                .build()?;
+            Ok(SsoClient {
+                config,
+                store,
+                http,
+                access_token: Mutex::new(None),
+            })
+        }
+
+        pub fn has_refresh_token(&self) -> bool {
+            matches!(self.stored_token(), Ok(Some(_)))
+        }
+
+        fn stored_token(&self) -> Result<Option<StoredToken>> {
+            Ok(self
+                .store
+                .load()?
+                .filter(|token| token.client_id == self.config.client_id))
+        }
+
+        pub fn authorize_url(&self, state: &str, pkce: &Pkce) -> String {
+            let mut url = url::Url::parse(&self.config.authorize_url)
+                .expect("SSO authorize url is not a valid url");
+            url.query_pairs_mut()
+                .append_pair("response_type", "code")
+                .append_pair("redirect_uri", &self.config.callback_url)
+                .append_pair("client_id", &self.config.client_id)
+                .append_pair("scope", &self.config.scopes.join(" "))
+                .append_pair("code_challenge", &pkce.challenge)
+                .append_pair("code_challenge_method", "S256")
+                .append_pair("state", state);
+            url.to_string()
+        }
+
+        /// Callers hold the `access_token` lock, so one request at a time uses the stored
+        /// refresh token.
+        async fn request_token(
+            &self,
+            cached: &mut Option<AccessToken>,
+            form: &[(&str, &str)],
+        ) -> Result<TokenReply> {
+            let res = self
+                .http
+                .post(&self.config.token_url)
+                .form(form)
+                .send()
+                .await?;
+            let status = res.status();
+            let body = res.text().await?;
+            if !status.is_success() {
+                let error = serde_json::from_str::<TokenErrorResponse>(&body).ok();
+                if error.is_some_and(|e| e.error == INVALID_GRANT) {
+                    return Ok(TokenReply::InvalidGrant);
+                }
+                return Err(
+                    format!("EVE SSO token request failed with {}: {}", status, body).into(),
+                );
+            }
+            let token: TokenResponse = serde_json::from_str(&body)?;
+
+            // EVE SSO rotates refresh tokens, the old one stops working.
+            self.store.save(&StoredToken {
+                client_id: self.config.client_id.clone(),
+                refresh_token: token.refresh_token.clone(),
+            })?;
+            *cached = Some(AccessToken {
+                token: token.access_token.clone(),
+                expires_at: Utc::now() + Duration::seconds(token.expires_in),
+            });
+            Ok(TokenReply::Granted(token.access_token))
+        }
+
+        async fn exchange_code(
+            &self,
+            cached: &mut Option<AccessToken>,
+            code: &str,
+            pkce: &Pkce,
+        ) -> Result<String> {
+            let reply = self
+                .request_token(
+                    cached,
+                    &[
+                        ("grant_type", "authorization_code"),
+                        ("code", code),
+                        ("client_id", &self.config.client_id),
+                        ("code_verifier", &pkce.verifier),
+                    ],
+                )
+                .await?;
+            match reply {
+                TokenReply::Granted(access_token) => Ok(access_token),
+                TokenReply::InvalidGrant => {
+                    Err("EVE SSO did not accept the login code, log in again".into())
+                }
+            }
+        }
+
+        async fn refresh(&self, cached: &mut Option<AccessToken>) -> Result<String> {
+            let stored = match self.stored_token()? {
+                Some(stored) => stored,
+                None => return Err("no EVE SSO refresh token stored, log in first".into()),
+            };
+            let reply = self
+                .request_token(
+                    cached,
+                    &[
+                        ("grant_type", "refresh_token"),
+                        ("refresh_token", &stored.refresh_token),
+                        ("client_id", &self.config.client_id),
+                    ],
+                )
+                .await?;
+            match reply {
+                TokenReply::Granted(access_token) => Ok(access_token),
+                // Revoked, or spent by another copy of the app: later runs would fail the same.
+                TokenReply::InvalidGrant => {
+                    println!("EVE SSO no longer accepts the saved login, log in again");
+                    self.store.clear()?;
+                    self.authorize(cached).await
+                }
+            }
+        }
+
+        /// Cached access token, refreshed through the stored refresh token when close to expiry.
+        /// The lock is held through the refresh: refresh tokens rotate, so callers waiting on it
+        /// get the new access token instead of refreshing with the spent refresh token.
+        pub async fn access_token(&self) -> Result<String> {
+            let mut cached = self.access_token.lock().await;
+            if let Some(token) = cached.as_ref() {
+                if token.expires_at - Duration::seconds(EXPIRY_MARGIN_SECONDS) > Utc::now() {
+                    return Ok(token.token.clone());
+                }
+            }
+            self.refresh(&mut cached).await
+        }
+
+        /// Interactive login: prints the SSO url and waits for the browser redirect
+        /// on the local callback address.
+        pub async fn login(&self) -> Result<()> {
+            let mut cached = self.access_token.lock().await;
+            self.authorize(&mut cached).await?;
+            Ok(())
+        }
+
+        async fn authorize(&self, cached: &mut Option<AccessToken>) -> Result<String> {
+            let callback = url::Url::parse(&self.config.callback_url)
+                .map_err(|e| format!("invalid SSO callback url: {}", e))?;
+            let address = format!(
+                "{}:{}",
+                callback.host_str().unwrap_or("localhost"),
+                callback.port_or_known_default().unwrap_or(80)
+            );
+            let listener = TcpListener::bind(&address).await?;
+
+            let pkce = Pkce::new();
+            let state = Pkce::new().verifier;
+            println!(
+                "Log in with EVE SSO in your browser:\n{}",
+                self.authorize_url(&state, &pkce)
+            );
+
+            let (mut socket, _) = listener.accept().await?;
+            let mut buffer = vec![0u8; 8192];
+            let read = socket.read(&mut buffer).await?;
+            let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+            let request_line = request.lines().next().unwrap_or_default();
+            let parsed = parse_callback_request(request_line);
+
+            let reply = match parsed {
+                Ok(_) => "Logged in, you can close this tab.",
+                Err(_) => "Login failed, check the terminal.",
+            };
+            socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        reply.len(),
+                        reply
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+
+            let (code, returned_state) = parsed?;
+            if returned_state != state {
+                return Err("EVE SSO callback state does not match, login aborted".into());
+            }
+            self.exchange_code(cached, &code, &pkce).await
+        }
+    }
+
+    /// Pulls `code` and `state` out of `GET /callback?code=...&state=... HTTP/1.1`.
+    pub fn parse_callback_request(request_line: &str) -> Result<(String, String)> {
+        let target = request_line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| format!("malformed SSO callback request: {}", request_line))?;
+        let url = url::Url::parse(&format!("http://localhost{}", target))
+            .map_err(|e| format!("malformed SSO callback request: {}", e))?;
+
+        let mut code = None;
+        let mut state = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "code" => code = Some(value.to_string()),
+                "state" => state = Some(value.to_string()),
+                _ => (),
+            }
+        }
+        match (code, state) {
+            (Some(code), Some(state)) => Ok((code, state)),
+            _ => Err(format!("SSO callback without code or state: {}", request_line).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::sso::sso::*;
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    pub fn temp_token_store(name: &str) -> TokenStore {
+        let path: PathBuf = std::env::temp_dir()
+            .join(format!(
+                "gescheftmacher-test-{}-{}",
+                std::process::id(),
+                name
+            ))
+            .join("sso_token.json");
+        let _ = std::fs::remove_file(&path);
+        TokenStore::new(path)
+    }
+
+    pub async fn mock_token_endpoint(server: &MockServer, access_token: &str) {
+        Mock::given(method("POST"))
+            .and(path("/v2/oauth/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": access_token,
+                "expires_in": 1199,
+                "token_type": "Bearer",
+                "refresh_token": "rotated-refresh-token",
+            })))
+            .mount(server)
+            .await;
+    }
+
+    pub fn mock_sso_client(server: &MockServer, store: TokenStore) -> SsoClient {
+        let mut config = SsoConfig::new("test-client");
+        config.token_url = format!("{}/v2/oauth/token", server.uri());
+        store
+            .save(&StoredToken {
+                client_id: "test-client".to_owned(),
+                refresh_token: "initial-refresh-token".to_owned(),
+            })
+            .unwrap();
+        SsoClient::new(config, store).unwrap()
+    }
+
+    #[test]
+    fn pkce_challenge_matches_rfc_7636_example() {
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(
+            pkce.challenge,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn authorize_url_carries_pkce_and_scope() {
+        let client = SsoClient::new(SsoConfig::new("abc"), temp_token_store("authorize")).unwrap();
+        let pkce = Pkce::from_verifier("verifier");
+        let url = client.authorize_url("xyz", &pkce);
+
+        assert!(url.starts_with(SSO_AUTHORIZE_URL));
+        assert!(url.contains("client_id=abc"));
+        assert!(url.contains("state=xyz"));
+        assert!(url.contains("code_challenge_method=S256"));
+        assert!(url.contains(&format!("code_challenge={}", pkce.challenge)));
+        assert!(url.contains("scope=esi-markets.structure_markets.v1"));
+    }
+
+    #[test]
+    fn callback_request_is_parsed() {
+        let parsed = parse_callback_request("GET /callback?code=c0de&state=st4te HTTP/1.1");
+        assert_eq!(parsed.unwrap(), ("c0de".to_owned(), "st4te".to_owned()));
+        assert!(parse_callback_request("GET /callback?error=access_denied HTTP/1.1").is_err());
+    }
+
+    #[test]
+    fn token_store_roundtrip() {
+        let store = temp_token_store("roundtrip");
+        assert_eq!(store.load().unwrap(), None);
+
+        let token = StoredToken {
+            client_id: "abc".to_owned(),
+            refresh_token: "refresh".to_owned(),
+        };
+        store.save(&token).unwrap();
+        assert_eq!(store.load().unwrap(), Some(token));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&store.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_stored_refresh_token() {
+        let server = MockServer::start().await;
+        mock_token_endpoint(&server, "fresh-access-token").await;
+        let store = temp_token_store("refresh");
+        let client = mock_sso_client(&server, store.clone());
+
+        assert_eq!(client.access_token().await.unwrap(), "fresh-access-token");
+        assert_eq!(
+            store.load().unwrap().unwrap().refresh_token,
+            "rotated-refresh-token"
+        );
+        // Second call is served from the cached access token.
+        assert_eq!(client.access_token().await.unwrap(), "fresh-access-token");
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn refresh_without_login_fails() {
+        let client = SsoClient::new(SsoConfig::new("abc"), temp_token_store("no-login")).unwrap();
+        assert!(!client.has_refresh_token());
+        assert!(client.access_token().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_refresh() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/oauth/token"))
+            .and(body_string_contains("refresh_token=initial-refresh-token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_delay(std::time::Duration::from_millis(100))
+                    .set_body_json(serde_json::json!({
+                        "access_token": "shared-access-token",
+                        "expires_in": 1199,
+                        "token_type": "Bearer",
+                        "refresh_token": "rotated-refresh-token",
+                    })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = mock_sso_client(&server, temp_token_store("concurrent"));
+
+        let (first, second) = tokio::join!(client.access_token(), client.access_token());
+
+        assert_eq!(first.unwrap(), "shared-access-token");
+        assert_eq!(second.unwrap(), "shared-access-token");
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_refresh_clears_token_and_logs_in_again() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/oauth/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": "Invalid refresh token. Token missing/expired.",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let store = temp_token_store("invalid-grant");
+        let mut client = mock_sso_client(&server, store.clone());
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        client.config.callback_url = format!("http://127.0.0.1:{}/callback", port);
+        // Plays the browser coming back from a login, with a state the client never issued.
+        let browser = tokio::spawn(async move {
+            loop {
+                if let Ok(mut socket) = tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+                    socket
+                        .write_all(b"GET /callback?code=c0de&state=wrong HTTP/1.1\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let mut reply = String::new();
+                    let _ = socket.read_to_string(&mut reply).await;
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        });
+
+        let err = client.access_token().await.unwrap_err();
+
+        browser.await.unwrap();
+        assert!(err.to_string().contains("state does not match"), "{}", err);
+        assert!(store.load().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn stalled_token_endpoint_times_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/oauth/token"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+        let mut config = SsoConfig::new("test-client");
+        config.token_url = format!("{}/v2/oauth/token", server.uri());
+        config.request_timeout = std::time::Duration::from_millis(100);
+        let store = temp_token_store("timeout");
+        store
+            .save(&StoredToken {
+                client_id: "test-client".to_owned(),
+                refresh_token: "initial-refresh-token".to_owned(),
+            })
+            .unwrap();
+        let client = SsoClient::new(config, store).unwrap();
+
+        assert!(client.access_token().await.is_err());
+    }
+}
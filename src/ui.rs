@@ -1,7 +1,14 @@
 pub mod ui {
-    use crate::datagetter::datagetter::TradeData;
-    use crate::ExtendedItemData;
+    use crate::cli::cli::DailyVolumeEstimate;
+    use crate::config::config::Config;
+    use crate::datagetter::datagetter::{ItemData, ItemMetadata, ItemMetadataFilter, TradeData};
+    use crate::filters::filters::{FilterReport, OpportunityFilter};
+    use crate::market_groups::market_groups::MarketGroupTree;
+    use crate::{DestinationSummary, ExtendedItemData};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use egui::Color32;
     use egui::Vec2;
+    use std::collections::{BTreeSet, HashSet};
     use egui_extras::{Column, TableBuilder};
     use struct_field_names_as_array::FieldNamesAsSlice;
 
@@ -10,12 +17,60 @@ pub mod ui {
     #[derive(Debug, PartialEq, Clone)]
     pub struct TradeItemViewManagerInitData {
         pub items: Vec<ExtendedItemData>,
+        /// One line per market, e.g. "12 items missing prices (1 failed batches) after 3.2s".
+        pub fetch_summary: Vec<String>,
+        /// Quotes older than this are coloured red in the `*_upd` columns.
+        pub max_quote_age: TimeDelta,
+        /// Feeds the side panel filter, `None` when the SDE has no market groups.
+        pub market_groups: Option<MarketGroupTree>,
+        /// Starts out as the command line filter, the side panel edits it.
+        pub metadata_filter: ItemMetadataFilter,
+        /// Thresholds to start with, adjustable from the side panel.
+        pub opportunity_filter: OpportunityFilter,
+        /// Lets the profile dropdown recompute the table, `None` hides the dropdown.
+        pub profile_switch: Option<ProfileSwitch>,
+    }
+    /// What the table was computed from, kept so a profile switch needs no refetch.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct ProfileSwitch {
+        /// The config without a profile applied, profiles are laid over it.
+        pub base_config: Config,
+        pub active_profile: Option<String>,
+        /// Locations the prices were fetched for.
+        pub fetched_markets: BTreeSet<i64>,
+        pub trade_items: Vec<ItemData>,
+        pub daily_volume: DailyVolumeEstimate,
+        pub hide_stale: bool,
+        /// Rows are exports, see [`Config::reversed`].
+        pub reverse: bool,
     }
     #[derive(Debug, PartialEq, Clone)]
     pub struct TradeItemViewManager {
         items: Vec<ExtendedItemData>,
+        fetch_summary: Vec<String>,
         table_headers: Vec<String>,
         table_rows: Vec<Vec<String>>,
+        /// Same shape as `table_rows`, `None` keeps the default text colour.
+        table_cell_colors: Vec<Vec<Option<Color32>>>,
+        market_groups: Option<MarketGroupTree>,
+        /// Groups holding at least one loaded item, plus their parents. Empty ones stay hidden.
+        populated_market_groups: HashSet<i32>,
+        selected_market_groups: BTreeSet<i32>,
+        /// Every group under the selection, `None` shows all rows.
+        visible_market_groups: Option<HashSet<i32>>,
+        metadata_filter: ItemMetadataFilter,
+        /// Choices for the side panel, taken from the loaded items.
+        known_categories: BTreeSet<String>,
+        known_meta_groups: BTreeSet<String>,
+        known_tech_levels: BTreeSet<i32>,
+        opportunity_filter: OpportunityFilter,
+        /// Per item, whether it passes `opportunity_filter`. Recomputed when a threshold changes.
+        opportunity_kept: Vec<bool>,
+        opportunity_report: FilterReport,
+        max_quote_age: TimeDelta,
+        profile_switch: Option<ProfileSwitch>,
+        /// Shown next to the profile dropdown, e.g. when the profile wants other stations.
+        profile_note: Option<String>,
     }
 
     pub trait FormatForDisplay {
@@ -41,6 +96,22 @@ pub mod ui {
         }
     }
 
+    impl FormatForDisplay for Option<f64> {
+        fn format_for_display(&self) -> String {
+            match self {
+                Some(value) => value.format_for_display(),
+                None => "-".to_owned(),
+            }
+        }
+
+        fn format_for_display_percentage(&self) -> String {
+            match self {
+                Some(value) => value.format_for_display_percentage(),
+                None => "-".to_owned(),
+            }
+        }
+    }
+
     impl FormatForDisplay for i64 {
         fn format_for_display(&self) -> String {
             let mut f: Formatter;
@@ -72,18 +143,25 @@ pub mod ui {
                 .map(|x| x.to_owned())
                 .collect();
 
+            // Every row lists the same destinations in config order.
+            let destination_names: Vec<String> = data
+                .items
+                .first()
+                .map(|item| item.destinations.iter().map(|d| d.name.clone()).collect())
+                .unwrap_or_default();
+
             let mut table_headers = vec![];
 
             for ef in &extended_data_fields {
-                if ef.to_string() == "jita_trade_data".to_owned() {
+                if ef.to_string() == "source_trade_data".to_owned() {
                     for tdf in &trade_data_fields {
                         match tdf.as_str() {
-                            "updated" => table_headers.push("j_upd".to_owned()),
-                            "weekly_movement" => table_headers.push("j_wkmov".to_owned()),
-                            "buy_max" => table_headers.push("j_buy".to_owned()),
-                            "buy_listed" => table_headers.push("j_buylist".to_owned()),
-                            "sell_min" => table_headers.push("j_sell".to_owned()),
-                            "sell_listed" => table_headers.push("j_selllist".to_owned()),
+                            "updated" => table_headers.push("src_upd".to_owned()),
+                            "weekly_movement" => table_headers.push("src_wkmov".to_owned()),
+                            "buy_max" => table_headers.push("src_buy".to_owned()),
+                            "buy_listed" => table_headers.push("src_buylist".to_owned()),
+                            "sell_min" => table_headers.push("src_sell".to_owned()),
+                            "sell_listed" => table_headers.push("src_selllist".to_owned()),
                             _ => table_headers.push(tdf.to_owned()),
                         }
                     }
@@ -99,12 +177,39 @@ pub mod ui {
                             _ => table_headers.push(tdf.to_owned()),
                         }
                     }
+                } else if ef == "metadata" {
+                    for mf in ItemMetadata::FIELD_NAMES_AS_SLICE {
+                        match *mf {
+                            "group_name" => table_headers.push("group".to_owned()),
+                            "category_name" => table_headers.push("category".to_owned()),
+                            "meta_group_name" => table_headers.push("meta_group".to_owned()),
+                            "meta_level" => table_headers.push("meta_lvl".to_owned()),
+                            "tech_level" => table_headers.push("tech_lvl".to_owned()),
+                            _ => table_headers.push(mf.to_string()),
+                        }
+                    }
+                } else if ef == "destinations" {
+                    for name in &destination_names {
+                        for df in DestinationSummary::FIELD_NAMES_AS_SLICE {
+                            if *df != "name" {
+                                table_headers.push(format!("{}_{}", name, df));
+                            }
+                        }
+                    }
+                } else if ef == "source" {
+                    table_headers.push("src".to_owned())
+                } else if ef == "destination" {
+                    table_headers.push("dest".to_owned())
+                } else if ef == "market_group_id" {
+                    table_headers.push("market_group".to_owned())
                 } else {
                     table_headers.push(ef.to_owned())
                 }
             }
 
+            let now = Utc::now();
             let mut table_rows: Vec<Vec<String>> = vec![];
+            let mut table_cell_colors: Vec<Vec<Option<Color32>>> = vec![];
 
             for entity in &data.items {
                 let mut row: Vec<String> = vec![];
@@ -113,94 +218,341 @@ pub mod ui {
                         "type_id" => row.push(entity.type_id.to_string()),
                         "type_volume" => row.push(entity.type_volume.to_string()),
                         "type_name" => row.push(entity.type_name.to_string()),
-                        "jita_trade_data" => {
-                            for tdf in &trade_data_fields {
-                                match tdf.as_str() {
-                                    "updated" => {
-                                        row.push(entity.jita_trade_data.updated.to_string())
-                                    }
-                                    "weekly_movement" => row.push(
-                                        entity.jita_trade_data.weekly_movement.format_for_display(),
-                                    ),
-                                    "buy_max" => row
-                                        .push(entity.jita_trade_data.buy_max.format_for_display()),
-                                    "buy_listed" => row.push(
-                                        entity.jita_trade_data.buy_listed.format_for_display(),
-                                    ),
-                                    "sell_min" => row
-                                        .push(entity.jita_trade_data.sell_min.format_for_display()),
-                                    "sell_listed" => row.push(
-                                        entity.jita_trade_data.sell_listed.format_for_display(),
-                                    ),
-                                    _ => panic!("SOME FIELDS MISSING!"),
-                                }
-                            }
-                        }
-                        "abroad_trade_data" => {
-                            for tdf in &trade_data_fields {
-                                match tdf.as_str() {
-                                    "updated" => {
-                                        row.push(entity.abroad_trade_data.updated.to_string())
-                                    }
-                                    "weekly_movement" => row.push(
-                                        entity
-                                            .abroad_trade_data
-                                            .weekly_movement
-                                            .format_for_display(),
-                                    ),
-                                    "buy_max" => row.push(
-                                        entity.abroad_trade_data.buy_max.format_for_display(),
-                                    ),
-                                    "buy_listed" => row.push(
-                                        entity.abroad_trade_data.buy_listed.format_for_display(),
-                                    ),
-                                    "sell_min" => row.push(
-                                        entity.abroad_trade_data.sell_min.format_for_display(),
-                                    ),
-                                    "sell_listed" => row.push(
-                                        entity.abroad_trade_data.sell_listed.format_for_display(),
-                                    ),
-                                    _ => panic!("SOME FIELDS MISSING!"),
-                                }
-                            }
-                        }
+                        "market_group_id" => row.push(
+                            match (&data.market_groups, entity.market_group_id) {
+                                (Some(tree), Some(id)) => tree
+                                    .get(id)
+                                    .map(|group| group.name.clone())
+                                    .unwrap_or_else(|| id.to_string()),
+                                (None, Some(id)) => id.to_string(),
+                                (_, None) => "-".to_owned(),
+                            },
+                        ),
+                        "not_seeded_abroad" => row.push(if entity.not_seeded_abroad {
+                            "not seeded".to_owned()
+                        } else {
+                            "".to_owned()
+                        }),
+                        "stale_quote" => row.push(if entity.stale_quote {
+                            "stale".to_owned()
+                        } else {
+                            "".to_owned()
+                        }),
+                        "metadata" => push_metadata_cells(&mut row, &entity.metadata),
+                        "source" => row.push(entity.source.clone()),
+                        "destination" => row.push(entity.destination.clone()),
+                        "destinations" => push_destination_cells(&mut row, &entity.destinations),
+                        "source_trade_data" => push_trade_data_cells(
+                            &mut row,
+                            &trade_data_fields,
+                            &entity.source_trade_data,
+                            now,
+                        ),
+                        "abroad_trade_data" => push_trade_data_cells(
+                            &mut row,
+                            &trade_data_fields,
+                            &entity.abroad_trade_data,
+                            now,
+                        ),
                         "abroad_stocked_ratio" => {
                             row.push(entity.abroad_stocked_ratio.format_for_display_percentage())
                         }
                         "shipping_price" => row.push(entity.shipping_price.format_for_display()),
-                        "jita_buy_with_tax" => {
-                            row.push(entity.jita_buy_with_tax.format_for_display())
+                        "source_buy_with_tax" => {
+                            row.push(entity.source_buy_with_tax.format_for_display())
                         }
+                        "landed_cost" => row.push(entity.landed_cost.format_for_display()),
                         "abroad_sell_taxed" => {
                             row.push(entity.abroad_sell_taxed.format_for_display())
                         }
                         "abroad_avg_daily" => {
                             row.push(entity.abroad_avg_daily.format_for_display())
                         }
-                        "profit_jita_buy_per_unit" => {
-                            row.push(entity.profit_jita_buy_per_unit.format_for_display())
+                        "abroad_median_price" => {
+                            row.push(entity.abroad_median_price.format_for_display())
+                        }
+                        "abroad_volatility" => {
+                            row.push(entity.abroad_volatility.format_for_display_percentage())
                         }
-                        "profit_jita_buy_daily" => {
-                            row.push(entity.profit_jita_buy_daily.format_for_display())
+                        "profit_buy_order_per_unit" => {
+                            row.push(entity.profit_buy_order_per_unit.format_for_display())
                         }
-                        "margin_jita_buy" => row.push(entity.margin_jita_buy.format_for_display()),
-                        "money_freeze_buy" => {
-                            row.push(entity.money_freeze_buy.format_for_display())
+                        "profit_buy_order_daily" => {
+                            row.push(entity.profit_buy_order_daily.format_for_display())
+                        }
+                        "margin_buy_order" => {
+                            row.push(entity.margin_buy_order.format_for_display())
+                        }
+                        "money_freeze_buy_order" => {
+                            row.push(entity.money_freeze_buy_order.format_for_display())
                         }
                         "freeze_rate" => row.push(entity.freeze_rate.format_for_display()),
+                        "abroad_buy_taxed" => {
+                            row.push(entity.abroad_buy_taxed.format_for_display())
+                        }
+                        "profit_instant_buy_per_unit" => {
+                            row.push(entity.profit_instant_buy_per_unit.format_for_display())
+                        }
+                        "profit_instant_buy_daily" => {
+                            row.push(entity.profit_instant_buy_daily.format_for_display())
+                        }
+                        "margin_instant_buy" => {
+                            row.push(entity.margin_instant_buy.format_for_display())
+                        }
+                        "money_freeze_instant_buy" => {
+                            row.push(entity.money_freeze_instant_buy.format_for_display())
+                        }
+                        "freeze_rate_instant_buy" => {
+                            row.push(entity.freeze_rate_instant_buy.format_for_display())
+                        }
+                        "profit_instant_sell_per_unit" => {
+                            row.push(entity.profit_instant_sell_per_unit.format_for_display())
+                        }
+                        "profit_instant_sell_daily" => {
+                            row.push(entity.profit_instant_sell_daily.format_for_display())
+                        }
+                        "margin_instant_sell" => {
+                            row.push(entity.margin_instant_sell.format_for_display())
+                        }
+                        "money_freeze_instant_sell" => {
+                            row.push(entity.money_freeze_instant_sell.format_for_display())
+                        }
+                        "freeze_rate_instant_sell" => {
+                            row.push(entity.freeze_rate_instant_sell.format_for_display())
+                        }
                         _ => panic!("SOME h-lvl probably custom fields missing!"),
                     }
                 }
-                table_rows.push(row)
+                let mut colors = vec![None; row.len()];
+                for (header, trade_data) in [
+                    ("src_upd", &entity.source_trade_data),
+                    ("ab_upd", &entity.abroad_trade_data),
+                ] {
+                    if let Some(index) = table_headers.iter().position(|h| h == header) {
+                        colors[index] = freshness_color(trade_data, data.max_quote_age, now);
+                    }
+                }
+                table_rows.push(row);
+                table_cell_colors.push(colors);
+            }
+            let mut populated_market_groups = HashSet::new();
+            if let Some(tree) = &data.market_groups {
+                for id in data.items.iter().filter_map(|item| item.market_group_id) {
+                    populated_market_groups.extend(tree.ancestors(id));
+                }
             }
+            let metadata = data.items.iter().map(|item| &item.metadata);
+            let known_categories = metadata
+                .clone()
+                .filter_map(|m| m.category_name.clone())
+                .collect();
+            let known_meta_groups = metadata
+                .clone()
+                .filter_map(|m| m.meta_group_name.clone())
+                .collect();
+            let known_tech_levels = metadata.filter_map(|m| m.tech_level).collect();
+            let (opportunity_kept, opportunity_report) =
+                data.opportunity_filter.apply(&data.items);
             TradeItemViewManager {
                 items: data.items,
+                fetch_summary: data.fetch_summary,
                 table_headers: table_headers,
                 table_rows: table_rows,
+                table_cell_colors,
+                market_groups: data.market_groups,
+                populated_market_groups,
+                selected_market_groups: BTreeSet::new(),
+                visible_market_groups: None,
+                metadata_filter: data.metadata_filter,
+                known_categories,
+                known_meta_groups,
+                known_tech_levels,
+                opportunity_filter: data.opportunity_filter,
+                opportunity_kept,
+                opportunity_report,
+                max_quote_age: data.max_quote_age,
+                profile_switch: data.profile_switch,
+                profile_note: None,
+            }
+        }
+
+        /// Recomputes every row with the named profile, `None` for the base config. Market
+        /// group selections survive, the item and opportunity filters come from the profile.
+        pub fn switch_profile(&mut self, name: Option<String>) {
+            let Some(mut switch) = self.profile_switch.take() else {
+                return;
+            };
+            let config = match &name {
+                Some(name) => switch.base_config.profile(name),
+                None => Ok(switch.base_config.clone()),
+            };
+            let config = match config {
+                Ok(config) if switch.reverse => config.reversed(),
+                Ok(config) => config,
+                Err(e) => {
+                    self.profile_note = Some(e.to_string());
+                    self.profile_switch = Some(switch);
+                    return;
+                }
+            };
+            let items = ExtendedItemData::from_items(
+                &switch.trade_items,
+                switch.daily_volume,
+                self.max_quote_age,
+                switch.hide_stale,
+                &config,
+            );
+            let profile_note = (config.market_locations() != switch.fetched_markets).then(|| {
+                "Prices are for the markets loaded at startup, \
+                restart with --profile to fetch this profile's markets"
+                    .to_owned()
+            });
+            switch.active_profile = name;
+            let selected_market_groups = std::mem::take(&mut self.selected_market_groups);
+            *self = TradeItemViewManager::new(TradeItemViewManagerInitData {
+                items,
+                fetch_summary: std::mem::take(&mut self.fetch_summary),
+                max_quote_age: self.max_quote_age,
+                market_groups: self.market_groups.take(),
+                metadata_filter: config.items.metadata_filter(),
+                opportunity_filter: config.thresholds,
+                profile_switch: Some(switch),
+            });
+            self.profile_note = profile_note;
+            for id in selected_market_groups {
+                self.set_market_group_selected(id, true);
+            }
+        }
+
+        pub fn set_opportunity_filter(&mut self, filter: OpportunityFilter) {
+            (self.opportunity_kept, self.opportunity_report) = filter.apply(&self.items);
+            self.opportunity_filter = filter;
+        }
+
+        pub fn set_market_group_selected(&mut self, id: i32, selected: bool) {
+            if selected {
+                self.selected_market_groups.insert(id);
+            } else {
+                self.selected_market_groups.remove(&id);
+            }
+            self.visible_market_groups = match &self.market_groups {
+                Some(tree) if !self.selected_market_groups.is_empty() => {
+                    let ids: Vec<i32> = self.selected_market_groups.iter().copied().collect();
+                    Some(tree.descendants_of_all(&ids))
+                }
+                _ => None,
+            };
+        }
+
+        pub fn clear_market_group_selection(&mut self) {
+            self.selected_market_groups.clear();
+            self.visible_market_groups = None;
+        }
+
+        pub fn is_row_visible(&self, index: usize) -> bool {
+            let item = &self.items[index];
+            if !self.opportunity_kept[index] || !self.metadata_filter.matches(&item.metadata) {
+                return false;
+            }
+            let Some(visible) = &self.visible_market_groups else {
+                return true;
+            };
+            item.market_group_id.is_some_and(|id| visible.contains(&id))
+        }
+    }
+
+    fn push_metadata_cells(row: &mut Vec<String>, metadata: &ItemMetadata) {
+        let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_owned());
+        let number = |value: Option<i32>| value.map_or_else(|| "-".to_owned(), |v| v.to_string());
+        for mf in ItemMetadata::FIELD_NAMES_AS_SLICE {
+            match *mf {
+                "group_name" => row.push(text(&metadata.group_name)),
+                "category_name" => row.push(text(&metadata.category_name)),
+                "meta_group_name" => row.push(text(&metadata.meta_group_name)),
+                "meta_level" => row.push(number(metadata.meta_level)),
+                "tech_level" => row.push(number(metadata.tech_level)),
+                _ => panic!("SOME METADATA FIELDS MISSING!"),
             }
         }
     }
 
+    fn push_destination_cells(row: &mut Vec<String>, destinations: &[DestinationSummary]) {
+        for destination in destinations {
+            for df in DestinationSummary::FIELD_NAMES_AS_SLICE {
+                match *df {
+                    "name" => (),
+                    "source" => row.push(destination.source.clone()),
+                    "landed_cost" => row.push(destination.landed_cost.format_for_display()),
+                    "not_seeded" => row.push(if destination.not_seeded {
+                        "not seeded".to_owned()
+                    } else {
+                        "".to_owned()
+                    }),
+                    "sell_taxed" => row.push(destination.sell_taxed.format_for_display()),
+                    "avg_daily" => row.push(destination.avg_daily.format_for_display()),
+                    "profit_daily" => row.push(destination.profit_daily.format_for_display()),
+                    "margin" => row.push(destination.margin.format_for_display()),
+                    "freeze_rate" => row.push(destination.freeze_rate.format_for_display()),
+                    _ => panic!("SOME DESTINATION FIELDS MISSING!"),
+                }
+            }
+        }
+    }
+
+    /// A market that did not report the item gets "-" in each of its columns.
+    fn push_trade_data_cells(
+        row: &mut Vec<String>,
+        trade_data_fields: &Vec<String>,
+        trade_data: &Option<TradeData>,
+        now: DateTime<Utc>,
+    ) {
+        for tdf in trade_data_fields {
+            let Some(td) = trade_data else {
+                row.push("-".to_owned());
+                continue;
+            };
+            match tdf.as_str() {
+                "updated" => row.push(match td.age(now) {
+                    Some(age) => format_age(age),
+                    None => "unknown".to_owned(),
+                }),
+                "weekly_movement" => row.push(td.weekly_movement.format_for_display()),
+                "buy_max" => row.push(td.buy_max.format_for_display()),
+                "buy_listed" => row.push(td.buy_listed.format_for_display()),
+                "sell_min" => row.push(td.sell_min.format_for_display()),
+                "sell_listed" => row.push(td.sell_listed.format_for_display()),
+                _ => panic!("SOME FIELDS MISSING!"),
+            }
+        }
+    }
+
+    pub fn format_age(age: TimeDelta) -> String {
+        if age < TimeDelta::minutes(1) {
+            return "just now".to_owned();
+        }
+        if age < TimeDelta::hours(1) {
+            return format!("{} min ago", age.num_minutes());
+        }
+        if age < TimeDelta::hours(48) {
+            return format!("{} h ago", age.num_hours());
+        }
+        return format!("{} d ago", age.num_days());
+    }
+
+    /// Green while younger than half of `max_age`, yellow up to `max_age`, red after.
+    pub fn freshness_color(
+        trade_data: &Option<TradeData>,
+        max_age: TimeDelta,
+        now: DateTime<Utc>,
+    ) -> Option<Color32> {
+        let age = trade_data.as_ref()?.age(now);
+        match age {
+            Some(age) if age <= max_age / 2 => Some(Color32::from_rgb(80, 180, 80)),
+            Some(age) if age <= max_age => Some(Color32::from_rgb(220, 180, 50)),
+            _ => Some(Color32::from_rgb(220, 70, 70)),
+        }
+    }
+
     pub fn render_ui(item_view_manager: TradeItemViewManager) -> eframe::Result<()> {
         let native_options = eframe::NativeOptions {
             viewport: egui::ViewportBuilder::default()
@@ -299,9 +651,34 @@ pub mod ui {
                     }
 
                     egui::widgets::global_dark_light_mode_buttons(ui);
+
+                    if let Some(data) = self.data.as_mut() {
+                        ui.add_space(16.0);
+                        show_profile_picker(ui, data);
+                    }
                 });
             });
 
+            if let Some(data) = self.data.as_mut() {
+                egui::SidePanel::left("filters_panel").show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        show_opportunity_filter(ui, data);
+                        ui.separator();
+                        show_metadata_filter(ui, data);
+                        if data.market_groups.is_some() {
+                            ui.separator();
+                            ui.heading("Market groups");
+                            if ui.button("Show all").clicked() {
+                                data.clear_market_group_selection();
+                            }
+                            for id in populated_children(data, None) {
+                                show_market_group_node(ui, data, id);
+                            }
+                        }
+                    });
+                });
+            }
+
             egui::CentralPanel::default().show(ctx, |ui| {
                 // The central panel the region left after adding TopPanel's and SidePanel's
                 ui.heading("eframe template");
@@ -324,6 +701,15 @@ pub mod ui {
 
                 ui.separator();
 
+                if let Some(data) = &self.data {
+                    for line in &data.fetch_summary {
+                        ui.label(line);
+                    }
+                    if let Some(note) = data.opportunity_report.hidden_note() {
+                        ui.label(egui::RichText::new(note).color(Color32::from_rgb(220, 180, 50)));
+                    }
+                }
+
                 ui.add(egui::github_link_file!(
                     "https://github.com/emilk/eframe_template/blob/main/",
                     "Source code."
@@ -339,20 +725,193 @@ pub mod ui {
         }
     }
 
+    fn show_profile_picker(ui: &mut egui::Ui, data: &mut TradeItemViewManager) {
+        let Some(switch) = &data.profile_switch else {
+            return;
+        };
+        let names = switch.base_config.profile_names();
+        if names.is_empty() {
+            return;
+        }
+        let active = switch.active_profile.clone();
+        let mut picked = active.clone();
+        egui::ComboBox::from_label("Profile")
+            .selected_text(active.as_deref().unwrap_or("(base config)"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut picked, None, "(base config)");
+                for name in names {
+                    ui.selectable_value(&mut picked, Some(name.clone()), name);
+                }
+            });
+        if picked != active {
+            data.switch_profile(picked);
+        }
+        if let Some(note) = &data.profile_note {
+            ui.label(egui::RichText::new(note).color(Color32::from_rgb(220, 180, 50)));
+        }
+    }
+
+    fn show_opportunity_filter(ui: &mut egui::Ui, data: &mut TradeItemViewManager) {
+        let mut filter = data.opportunity_filter.clone();
+        ui.heading("Opportunities");
+        ui.checkbox(&mut filter.enabled, "Only show opportunities");
+        ui.add_enabled_ui(filter.enabled, |ui| {
+            egui::Grid::new("opportunity_thresholds").show(ui, |ui| {
+                ui.label("Min sell margin");
+                ui.add(egui::DragValue::new(&mut filter.min_sell_margin).speed(0.01));
+                ui.end_row();
+                ui.label("Min daily profit");
+                ui.add(egui::DragValue::new(&mut filter.min_daily_profit).speed(100_000.0));
+                ui.end_row();
+                ui.label("Min freeze rate");
+                ui.add(egui::DragValue::new(&mut filter.min_freeze_rate).speed(0.01));
+                ui.end_row();
+                ui.label("Max market rate");
+                ui.add(egui::DragValue::new(&mut filter.max_market_rate).speed(0.1));
+                ui.end_row();
+                ui.label("Min daily volume");
+                ui.add(egui::DragValue::new(&mut filter.min_daily_volume));
+                ui.end_row();
+            });
+            ui.checkbox(&mut filter.keep_not_seeded, "Keep items not seeded abroad");
+        });
+        if filter != data.opportunity_filter {
+            data.set_opportunity_filter(filter);
+        }
+        for line in data.opportunity_report.lines() {
+            ui.label(line);
+        }
+    }
+
+    fn show_metadata_filter(ui: &mut egui::Ui, data: &mut TradeItemViewManager) {
+        let filter = &mut data.metadata_filter;
+        ui.heading("Item filters");
+        ui.collapsing("Category", |ui| {
+            for name in &data.known_categories {
+                toggle_in_list(ui, &mut filter.categories, name);
+            }
+        });
+        ui.collapsing("Meta group", |ui| {
+            for name in &data.known_meta_groups {
+                toggle_in_list(ui, &mut filter.meta_groups, name);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tech level");
+            for level in &data.known_tech_levels {
+                let mut checked = filter.tech_levels.contains(level);
+                if ui.checkbox(&mut checked, level.to_string()).changed() {
+                    if checked {
+                        filter.tech_levels.push(*level);
+                    } else {
+                        filter.tech_levels.retain(|l| l != level);
+                    }
+                }
+            }
+        });
+        optional_bound(ui, "Min meta level", &mut filter.min_meta_level);
+        optional_bound(ui, "Max meta level", &mut filter.max_meta_level);
+        if !filter.groups.is_empty() {
+            ui.label(format!("Groups: {}", filter.groups.join(", ")));
+        }
+        if ui.button("Clear item filters").clicked() {
+            *filter = ItemMetadataFilter::default();
+        }
+    }
+
+    fn toggle_in_list(ui: &mut egui::Ui, list: &mut Vec<String>, name: &String) {
+        let mut checked = list.iter().any(|n| n.eq_ignore_ascii_case(name));
+        if ui.checkbox(&mut checked, name).changed() {
+            if checked {
+                list.push(name.clone());
+            } else {
+                list.retain(|n| !n.eq_ignore_ascii_case(name));
+            }
+        }
+    }
+
+    /// A checkbox switching the bound on and off, with its value next to it.
+    fn optional_bound(ui: &mut egui::Ui, label: &str, bound: &mut Option<i32>) {
+        ui.horizontal(|ui| {
+            let mut enabled = bound.is_some();
+            if ui.checkbox(&mut enabled, label).changed() {
+                *bound = if enabled { Some(0) } else { None };
+            }
+            if let Some(value) = bound {
+                ui.add(egui::DragValue::new(value).clamp_range(0..=20));
+            }
+        });
+    }
+
+    /// Children of `parent` (roots for `None`) that lead to at least one loaded item.
+    fn populated_children(data: &TradeItemViewManager, parent: Option<i32>) -> Vec<i32> {
+        let Some(tree) = &data.market_groups else {
+            return vec![];
+        };
+        let ids = match parent {
+            Some(id) => tree.children(id),
+            None => tree.roots(),
+        };
+        ids
+            .iter()
+            .filter(|id| data.populated_market_groups.contains(id))
+            .copied()
+            .collect()
+    }
+
+    fn show_market_group_node(ui: &mut egui::Ui, data: &mut TradeItemViewManager, id: i32) {
+        let name = data
+            .market_groups
+            .as_ref()
+            .and_then(|tree| tree.get(id))
+            .map(|group| group.name.clone())
+            .unwrap_or_default();
+        let children = populated_children(data, Some(id));
+        let mut selected = data.selected_market_groups.contains(&id);
+        if children.is_empty() {
+            if ui.checkbox(&mut selected, name).changed() {
+                data.set_market_group_selected(id, selected);
+            }
+            return;
+        }
+        egui::collapsing_header::CollapsingState::load_with_default_open(
+            ui.ctx(),
+            ui.make_persistent_id(("market_group", id)),
+            false,
+        )
+        .show_header(ui, |ui| {
+            if ui.checkbox(&mut selected, name).changed() {
+                data.set_market_group_selected(id, selected);
+            }
+        })
+        .body(|ui| {
+            for child in children {
+                show_market_group_node(ui, data, child);
+            }
+        });
+    }
+
     fn show_table(ctx: &mut TemplateApp, ui: &mut egui::Ui) {
         // TODO: add filtering to ui by adding checkboxes
         let filtered = vec![
             "shipping_price".to_owned(),
-            "profit_jita_buy_per_unit".to_owned(),
+            "profit_buy_order_per_unit".to_owned(),
             "type_id".to_owned(),
             "type_volume".to_owned(),
         ];
 
     
 
-        let column_quantity = ctx.data.clone().unwrap().table_headers.len() - filtered.len();
-        let mut headers = ctx.data.clone().unwrap().table_headers;
-        let mut rows = ctx.data.clone().unwrap().table_rows;
+        let data = ctx.data.as_ref().unwrap();
+        let column_quantity = data.table_headers.len() - filtered.len();
+        let mut headers = data.table_headers.clone();
+        let mut rows = data.table_rows.clone();
+        let mut cell_colors = data.table_cell_colors.clone();
+        let visible_rows: Vec<bool> = (0..rows.len()).map(|i| data.is_row_visible(i)).collect();
+        let mut visible = visible_rows.iter();
+        rows.retain(|_| *visible.next().unwrap());
+        let mut visible = visible_rows.iter();
+        cell_colors.retain(|_| *visible.next().unwrap());
 
         filtered.iter().for_each(|f| {
             if let Some(index) = headers.iter().position(|h| h == f) {
@@ -360,6 +919,9 @@ pub mod ui {
                 rows.iter_mut().for_each(|r| {
                     r.remove(index);
                 });
+                cell_colors.iter_mut().for_each(|r| {
+                    r.remove(index);
+                });
                 
             }
         });
@@ -381,11 +943,16 @@ pub mod ui {
                     }
                 })
                 .body(|mut body| {
-                    for r in rows {
+                    for (r, colors) in rows.into_iter().zip(cell_colors) {
                         body.row(30.0, |mut row| {
-                            for cell in r {
-                                row.col(|ui| {
-                                    ui.label(cell);
+                            for (cell, color) in r.into_iter().zip(colors) {
+                                row.col(|ui| match color {
+                                    Some(color) => {
+                                        ui.label(egui::RichText::new(cell).color(color));
+                                    }
+                                    None => {
+                                        ui.label(cell);
+                                    }
                                 });
                             }
                         });
@@ -413,7 +980,18 @@ pub mod ui {
 mod tests {
     use numfmt::Formatter;
 
-    use crate::ui::ui::FormatForDisplay;
+    use crate::cli::cli::DailyVolumeEstimate;
+    use crate::config::config::Config;
+    use crate::datagetter::datagetter::{ItemData, ItemMetadata, ItemMetadataFilter, TradeData};
+    use crate::filters::filters::OpportunityFilter;
+    use crate::market_groups::tests::sample_tree;
+    use crate::ui::ui::{
+        format_age, FormatForDisplay, ProfileSwitch, TradeItemViewManager,
+        TradeItemViewManagerInitData,
+    };
+    use crate::datagetter::tests::markets;
+    use crate::ExtendedItemData;
+    use chrono::TimeDelta;
 
     #[test]
     fn format_thouthands() {
@@ -438,6 +1016,175 @@ mod tests {
         assert_eq!("1 000 000.0", 1000000.00.format_for_display())
     }
     #[test]
+    fn format_missing_value() {
+        assert_eq!("-", None::<f64>.format_for_display());
+        assert_eq!("4.85", Some(4.85).format_for_display());
+    }
+    #[test]
+    fn format_relative_age() {
+        assert_eq!("just now", format_age(TimeDelta::seconds(20)));
+        assert_eq!("42 min ago", format_age(TimeDelta::minutes(42)));
+        assert_eq!("5 h ago", format_age(TimeDelta::minutes(5 * 60 + 59)));
+        assert_eq!("3 d ago", format_age(TimeDelta::days(3)));
+    }
+    #[test]
+    fn market_group_selection_filters_rows() {
+        let item = |type_id, market_group_id| {
+            let data = ItemData {
+                type_id,
+                type_volume: 2500.0,
+                type_name: type_id.to_string(),
+                market_group_id,
+                metadata: ItemMetadata::default(),
+                markets: markets(None, None),
+            };
+            ExtendedItemData::new(
+                data,
+                DailyVolumeEstimate::Heuristic,
+                TimeDelta::hours(3),
+                &Config::default(),
+            )
+        };
+        let mut manager = TradeItemViewManager::new(TradeItemViewManagerInitData {
+            items: vec![item(603, Some(64)), item(2281, Some(1)), item(34, None)],
+            fetch_summary: vec![],
+            max_quote_age: TimeDelta::hours(3),
+            market_groups: Some(sample_tree()),
+            metadata_filter: ItemMetadataFilter::default(),
+            opportunity_filter: OpportunityFilter {
+                enabled: false,
+                ..OpportunityFilter::default()
+            },
+            profile_switch: None,
+        });
+        let visible = |manager: &TradeItemViewManager| -> Vec<usize> {
+            (0..3).filter(|i| manager.is_row_visible(*i)).collect()
+        };
+        assert_eq!(visible(&manager), vec![0, 1, 2]);
+
+        manager.set_market_group_selected(4, true);
+        assert_eq!(visible(&manager), vec![0]);
+        manager.set_market_group_selected(9, true);
+        assert_eq!(visible(&manager), vec![0, 1]);
+
+        manager.clear_market_group_selection();
+        assert_eq!(visible(&manager), vec![0, 1, 2]);
+    }
+    #[test]
+    fn metadata_filter_hides_rows() {
+        let item = |type_id, category: &str| {
+            let data = ItemData {
+                type_id,
+                type_volume: 1.0,
+                type_name: type_id.to_string(),
+                market_group_id: None,
+                metadata: ItemMetadata {
+                    category_name: Some(category.to_owned()),
+                    ..ItemMetadata::default()
+                },
+                markets: markets(None, None),
+            };
+            ExtendedItemData::new(
+                data,
+                DailyVolumeEstimate::Heuristic,
+                TimeDelta::hours(3),
+                &Config::default(),
+            )
+        };
+        let manager = TradeItemViewManager::new(TradeItemViewManagerInitData {
+            items: vec![item(2281, "Module"), item(34, "Material")],
+            fetch_summary: vec![],
+            max_quote_age: TimeDelta::hours(3),
+            market_groups: None,
+            metadata_filter: ItemMetadataFilter {
+                categories: vec!["Material".to_owned()],
+                ..ItemMetadataFilter::default()
+            },
+            opportunity_filter: OpportunityFilter {
+                enabled: false,
+                ..OpportunityFilter::default()
+            },
+            profile_switch: None,
+        });
+
+        assert!(!manager.is_row_visible(0));
+        assert!(manager.is_row_visible(1));
+    }
+    #[test]
+    fn switching_profile_recomputes_without_refetch() {
+        let config = Config::parse(
+            "[thresholds]
+            enabled = true
+            min_daily_profit = 0.0
+            min_freeze_rate = 0.0
+            max_market_rate = 1000.0
+            min_daily_volume = 0.0
+
+            [profiles.jf.freight]
+            price_per_m3 = 100.0",
+        )
+        .unwrap();
+        let item = ItemData {
+            type_id: 603,
+            type_volume: 2500.0,
+            type_name: "Merlin".to_owned(),
+            market_group_id: Some(64),
+            metadata: ItemMetadata::default(),
+            markets: markets(Some(TradeData {
+                updated: None,
+                weekly_movement: 700.0,
+                buy_max: 1_000_000.0,
+                buy_listed: 50,
+                sell_min: 1_200_000.0,
+                sell_listed: 80,
+            }), Some(TradeData {
+                updated: None,
+                weekly_movement: 70.0,
+                buy_max: 0.0,
+                buy_listed: 0,
+                sell_min: 3_000_000.0,
+                sell_listed: 7,
+            })),
+        };
+        let hardener = ItemData {
+            type_id: 2281,
+            market_group_id: Some(1),
+            ..item.clone()
+        };
+        let max_quote_age = TimeDelta::hours(3);
+        let mut manager = TradeItemViewManager::new(TradeItemViewManagerInitData {
+            items: ExtendedItemData::from_items(
+                &[item.clone(), hardener.clone()],
+                DailyVolumeEstimate::Heuristic,
+                max_quote_age,
+                false,
+                &config,
+            ),
+            fetch_summary: vec![],
+            max_quote_age,
+            market_groups: Some(sample_tree()),
+            metadata_filter: ItemMetadataFilter::default(),
+            opportunity_filter: config.thresholds.clone(),
+            profile_switch: Some(ProfileSwitch {
+                fetched_markets: config.market_locations(),
+                base_config: config,
+                active_profile: None,
+                trade_items: vec![item, hardener],
+                daily_volume: DailyVolumeEstimate::Heuristic,
+                hide_stale: false,
+                reverse: false,
+            }),
+        });
+        manager.set_market_group_selected(4, true);
+        // 2500 m3 at 850 ISK each eats the whole margin.
+        assert!(!manager.is_row_visible(0));
+
+        manager.switch_profile(Some("jf".to_owned()));
+
+        assert!(manager.is_row_visible(0));
+        assert!(!manager.is_row_visible(1));
+    }
+    #[test]
     fn long_with_float() {
         assert_eq!("1 000 000.55", 1000000.55.format_for_display())
     }